@@ -1,6 +1,6 @@
 // examples/api_consumer.rs
 
-use httpageboy::{Request, Response, Rt, Server, StatusCode};
+use httpageboy::{Headers, Request, Response, Rt, Server, StatusCode};
 
 // ---- Synchronous Implementation ----
 #[cfg(feature = "sync")]
@@ -12,7 +12,8 @@ mod sync_impl {
         Response {
             status: StatusCode::Ok.to_string(),
             content_type: "text/plain".to_string(),
-            content: "Welcome to the SYNC API consumer example!".as_bytes().to_vec(),
+            content: "Welcome to the SYNC API consumer example!".into(),
+            headers: Headers::new(),
         }
     }
 
@@ -20,17 +21,19 @@ mod sync_impl {
         Response {
             status: StatusCode::Ok.to_string(),
             content_type: "text/plain".to_string(),
-            content: "This is a SYNC GET response.".as_bytes().to_vec(),
+            content: "This is a SYNC GET response.".into(),
+            headers: Headers::new(),
         }
     }
 
     fn demo_handle_post(request: &Request) -> Response {
-        let body_str = String::from_utf8_lossy(request.body.as_bytes());
+        let body_str = request.body_str();
         let response_body = format!("Received SYNC POST with body: {}", body_str);
         Response {
             status: StatusCode::Ok.to_string(),
             content_type: "text/plain".to_string(),
-            content: response_body.as_bytes().to_vec(),
+            content: response_body.into(),
+            headers: Headers::new(),
         }
     }
 
@@ -59,7 +62,8 @@ mod async_impl {
         Response {
             status: StatusCode::Ok.to_string(),
             content_type: "text/plain".to_string(),
-            content: "Welcome to the ASYNC API consumer example!".as_bytes().to_vec(),
+            content: "Welcome to the ASYNC API consumer example!".into(),
+            headers: Headers::new(),
         }
     }
 
@@ -67,17 +71,19 @@ mod async_impl {
         Response {
             status: StatusCode::Ok.to_string(),
             content_type: "text/plain".to_string(),
-            content: "This is an ASYNC GET response.".as_bytes().to_vec(),
+            content: "This is an ASYNC GET response.".into(),
+            headers: Headers::new(),
         }
     }
 
     async fn demo_handle_post(request: &Request) -> Response {
-        let body_str = String::from_utf8_lossy(request.body.as_bytes());
+        let body_str = request.body_str();
         let response_body = format!("Received ASYNC POST with body: {}", body_str);
         Response {
             status: StatusCode::Ok.to_string(),
             content_type: "text/plain".to_string(),
-            content: response_body.as_bytes().to_vec(),
+            content: response_body.into(),
+            headers: Headers::new(),
         }
     }
 
@@ -0,0 +1,164 @@
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+
+use futures::Stream;
+
+/// Size of the pieces a file body is read and sent in.
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+/// The zero-sized chunk closing a chunked body, without trailers.
+pub const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+
+/// The payload of a [`Response`](crate::core::response::Response).
+///
+/// `Bytes` and `File` have a known length and are sent with
+/// `Content-Length`; `Chunks` and `Stream` are produced while the response
+/// is written, so they go out with `Transfer-Encoding: chunked`.
+pub enum Body {
+  Bytes(Vec<u8>),
+  File(File),
+  Chunks(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+  Stream(Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>),
+}
+
+impl Body {
+  /// A body produced piece by piece by an iterator.
+  pub fn chunks<I>(chunks: I) -> Self
+  where
+    I: IntoIterator<Item = Vec<u8>>,
+    I::IntoIter: Send + 'static,
+  {
+    Body::Chunks(Box::new(chunks.into_iter()))
+  }
+
+  /// A body produced piece by piece by an async stream.
+  pub fn stream<S>(stream: S) -> Self
+  where
+    S: Stream<Item = Vec<u8>> + Send + 'static,
+  {
+    Body::Stream(Box::pin(stream))
+  }
+
+  /// The length in bytes, when known before sending.
+  pub fn len(&self) -> Option<u64> {
+    match self {
+      Body::Bytes(bytes) => Some(bytes.len() as u64),
+      Body::File(file) => file.metadata().ok().map(|m| m.len()),
+      Body::Chunks(_) | Body::Stream(_) => None,
+    }
+  }
+
+  /// Whether the body is known to be empty.
+  pub fn is_empty(&self) -> bool {
+    self.len() == Some(0)
+  }
+
+  /// The buffered bytes, for a `Bytes` body.
+  pub fn as_bytes(&self) -> Option<&[u8]> {
+    match self {
+      Body::Bytes(bytes) => Some(bytes),
+      _ => None,
+    }
+  }
+
+  /// Writes the body, chunk-encoding it when its length is unknown.
+  pub fn write_to<W: Write>(self, out: &mut W) -> io::Result<()> {
+    match self {
+      Body::Bytes(bytes) => out.write_all(&bytes),
+      Body::File(mut file) => io::copy(&mut file, out).map(|_| ()),
+      Body::Chunks(chunks) => {
+        for chunk in chunks {
+          write_chunk(out, &chunk)?;
+        }
+        out.write_all(LAST_CHUNK)
+      }
+      Body::Stream(stream) => {
+        for chunk in futures::executor::block_on_stream(stream) {
+          write_chunk(out, &chunk)?;
+        }
+        out.write_all(LAST_CHUNK)
+      }
+    }
+  }
+}
+
+/// Frames `data` as one chunk of a chunked body. Empty data yields nothing,
+/// since an empty chunk would end the body.
+pub fn encode_chunk(data: &[u8]) -> Vec<u8> {
+  if data.is_empty() {
+    return Vec::new();
+  }
+  let mut frame = format!("{:X}\r\n", data.len()).into_bytes();
+  frame.extend_from_slice(data);
+  frame.extend_from_slice(b"\r\n");
+  frame
+}
+
+fn write_chunk<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+  out.write_all(&encode_chunk(data))
+}
+
+/// Reads the next piece of a file body, `None` at its end.
+pub fn read_file_chunk(file: &mut File) -> io::Result<Option<Vec<u8>>> {
+  let mut buffer = vec![0; FILE_CHUNK_SIZE];
+  let read = file.read(&mut buffer)?;
+  if read == 0 {
+    return Ok(None);
+  }
+  buffer.truncate(read);
+  Ok(Some(buffer))
+}
+
+impl Default for Body {
+  fn default() -> Self {
+    Body::Bytes(Vec::new())
+  }
+}
+
+impl Debug for Body {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Body::Bytes(bytes) => write!(f, "{:?}", bytes),
+      Body::File(file) => write!(f, "File({:?})", file),
+      Body::Chunks(_) => f.write_str("Chunks(..)"),
+      Body::Stream(_) => f.write_str("Stream(..)"),
+    }
+  }
+}
+
+impl From<Vec<u8>> for Body {
+  fn from(bytes: Vec<u8>) -> Self {
+    Body::Bytes(bytes)
+  }
+}
+
+impl From<&[u8]> for Body {
+  fn from(bytes: &[u8]) -> Self {
+    Body::Bytes(bytes.to_vec())
+  }
+}
+
+impl<const N: usize> From<&[u8; N]> for Body {
+  fn from(bytes: &[u8; N]) -> Self {
+    Body::Bytes(bytes.to_vec())
+  }
+}
+
+impl From<String> for Body {
+  fn from(text: String) -> Self {
+    Body::Bytes(text.into_bytes())
+  }
+}
+
+impl From<&str> for Body {
+  fn from(text: &str) -> Self {
+    Body::Bytes(text.as_bytes().to_vec())
+  }
+}
+
+impl From<File> for Body {
+  fn from(file: File) -> Self {
+    Body::File(file)
+  }
+}
@@ -0,0 +1,79 @@
+#![cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+
+use crate::core::status_code::StatusCode;
+use crate::{Request, Response};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Renders the answer to an error status, e.g. as a JSON problem document
+/// or an HTML page.
+pub type ErrorRenderer = Arc<dyn Fn(&Request, StatusCode) -> Response + Send + Sync>;
+
+/// Renderers for the error answers the server produces itself: unrouted
+/// requests (404, 405, 501), requests that can't be read or parsed (400,
+/// 408, 411, 413, 414, 431, 505) and handlers running out of time (503).
+/// Responses returned by handlers are sent as they are.
+///
+/// For a request that could not be parsed the renderer gets an empty
+/// [`Request`]. A renderer can't change the status: whatever it returns is
+/// sent with the status being rendered.
+#[derive(Clone, Default)]
+pub struct ErrorPages {
+  by_status: HashMap<StatusCode, ErrorRenderer>,
+  default: Option<ErrorRenderer>,
+}
+
+impl ErrorPages {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Renders `status` with `render`.
+  pub fn set<F>(&mut self, status: StatusCode, render: F)
+  where
+    F: Fn(&Request, StatusCode) -> Response + Send + Sync + 'static,
+  {
+    self.by_status.insert(status, Arc::new(render));
+  }
+
+  /// Renders every error status without a renderer of its own with `render`.
+  pub fn set_default<F>(&mut self, render: F)
+  where
+    F: Fn(&Request, StatusCode) -> Response + Send + Sync + 'static,
+  {
+    self.default = Some(Arc::new(render));
+  }
+
+  /// The rendered answer to `status`, if there is a renderer for it.
+  pub fn render(&self, request: &Request, status: StatusCode) -> Option<Response> {
+    let render = self.by_status.get(&status).or(self.default.as_ref())?;
+    let mut response = render(request, status);
+    response.status = status.to_string();
+    Some(response)
+  }
+
+  /// `response` rendered anew when it carries an error status with a
+  /// renderer, otherwise unchanged. Headers the server set on it, like
+  /// `Allow`, are kept unless the renderer sets them too.
+  pub fn apply(&self, request: &Request, response: Response) -> Response {
+    let Some(status) = response.status_code().filter(|s| s.code() >= 400) else {
+      return response;
+    };
+    match self.render(request, status) {
+      Some(mut rendered) => {
+        for (name, value) in response.headers.iter() {
+          if !rendered.headers.contains(name) {
+            rendered.headers.append(name, value);
+          }
+        }
+        rendered
+      }
+      None => response,
+    }
+  }
+}
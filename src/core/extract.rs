@@ -0,0 +1,453 @@
+#![cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+
+use crate::core::headers::Headers;
+use crate::core::into_response::IntoResponse;
+use crate::core::status_code::StatusCode;
+use crate::Request;
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+/// A handler argument built from the request before the handler runs.
+///
+/// A handler given to `handler!` can take any number of extractors instead
+/// of `&Request`, e.g. `fn get_user(Path(id): Path<u64>, Query(page):
+/// Query<Page>)`. When one of them can't be built the request is answered
+/// with its rejection and the handler isn't called.
+pub trait FromRequest: Sized {
+  type Rejection: IntoResponse;
+
+  fn from_request(request: &Request) -> Result<Self, Self::Rejection>;
+}
+
+/// What most extractors answer with when they refuse a request: the status
+/// and a `text/plain` explanation.
+pub type Rejection = (StatusCode, String);
+
+/// `None` when `T` can't be built, instead of answering the request.
+impl<T: FromRequest> FromRequest for Option<T> {
+  type Rejection = Infallible;
+
+  fn from_request(request: &Request) -> Result<Self, Infallible> {
+    Ok(T::from_request(request).ok())
+  }
+}
+
+impl FromRequest for Headers {
+  type Rejection = Infallible;
+
+  fn from_request(request: &Request) -> Result<Self, Infallible> {
+    Ok(request.headers.clone())
+  }
+}
+
+/// The raw body.
+impl FromRequest for Vec<u8> {
+  type Rejection = Infallible;
+
+  fn from_request(request: &Request) -> Result<Self, Infallible> {
+    Ok(request.body.clone())
+  }
+}
+
+/// The body as text; a body that isn't UTF-8 is answered with 400 Bad Request.
+impl FromRequest for String {
+  type Rejection = Rejection;
+
+  fn from_request(request: &Request) -> Result<Self, Rejection> {
+    String::from_utf8(request.body.clone())
+      .map_err(|_| (StatusCode::BadRequest, "request body is not valid UTF-8".to_string()))
+  }
+}
+
+// --- Shared state ---
+
+/// Values shared by every handler of a server, one per type, handed out by
+/// the [`State`] extractor and [`Request::state`].
+#[derive(Clone, Default)]
+pub struct States(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl States {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds `value`, replacing any earlier value of the same type.
+  pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+    self.0.insert(TypeId::of::<T>(), Arc::new(value));
+  }
+
+  pub fn get<T: 'static>(&self) -> Option<&T> {
+    self.0.get(&TypeId::of::<T>())?.downcast_ref()
+  }
+}
+
+/// A clone of the server's state of type `T`, e.g. `State<Arc<Db>>`. A
+/// missing state is a setup mistake and is answered with 500 Internal
+/// Server Error.
+#[derive(Clone, Debug)]
+pub struct State<T>(pub T);
+
+impl<T: Clone + Send + Sync + 'static> FromRequest for State<T> {
+  type Rejection = Rejection;
+
+  fn from_request(request: &Request) -> Result<Self, Rejection> {
+    match request.state::<T>() {
+      Some(value) => Ok(State(value.clone())),
+      None => Err((
+        StatusCode::InternalServerError,
+        format!("no state of type {} was added to the server", type_name::<T>()),
+      )),
+    }
+  }
+}
+
+// --- Headers ---
+
+/// A header decoded into a type of its own, taken by the [`Header`]
+/// extractor.
+pub trait TypedHeader: Sized {
+  const NAME: &'static str;
+
+  /// The header from its value; `None` when the value is malformed.
+  fn decode(value: &str) -> Option<Self>;
+}
+
+/// The typed header `T`, e.g. `Header<UserAgent>`. A missing or malformed
+/// header is answered with 400 Bad Request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header<T>(pub T);
+
+impl<T: TypedHeader> FromRequest for Header<T> {
+  type Rejection = Rejection;
+
+  fn from_request(request: &Request) -> Result<Self, Rejection> {
+    let Some(value) = request.headers.get(T::NAME) else {
+      return Err((StatusCode::BadRequest, format!("missing header {}", T::NAME)));
+    };
+    T::decode(value)
+      .map(Header)
+      .ok_or_else(|| (StatusCode::BadRequest, format!("invalid header {}", T::NAME)))
+  }
+}
+
+macro_rules! text_headers {
+  ($($ty:ident => $name:literal),* $(,)?) => {
+    $(
+      #[doc = concat!("The `", $name, "` header, as sent.")]
+      #[derive(Clone, Debug, PartialEq)]
+      pub struct $ty(pub String);
+
+      impl TypedHeader for $ty {
+        const NAME: &'static str = $name;
+
+        fn decode(value: &str) -> Option<Self> {
+          Some($ty(value.to_string()))
+        }
+      }
+    )*
+  };
+}
+
+text_headers! {
+  Accept => "Accept",
+  Authorization => "Authorization",
+  ContentType => "Content-Type",
+  Host => "Host",
+  UserAgent => "User-Agent",
+}
+
+/// Whether the request's `Content-Type`, parameters aside, passes `accept`.
+#[cfg(feature = "serde")]
+fn media_type_is(request: &Request, accept: impl Fn(&str) -> bool) -> bool {
+  let Some(value) = request.headers.content_type() else {
+    return false;
+  };
+  let media_type = value.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+  accept(&media_type)
+}
+
+// --- Path, query and form data ---
+
+/// The path parameters of the route, e.g. `Path<u64>` for `/users/{id}` or
+/// a struct with a field per parameter. Parameters that don't fit `T` are
+/// answered with 400 Bad Request.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: serde::de::DeserializeOwned> FromRequest for Path<T> {
+  type Rejection = Rejection;
+
+  fn from_request(request: &Request) -> Result<Self, Rejection> {
+    let params = request.path_params().iter().map(|(k, v)| (k.as_str(), v.as_str()));
+    de::from_pairs(params)
+      .map(Path)
+      .map_err(|e| (StatusCode::BadRequest, format!("invalid path parameters: {}", e)))
+  }
+}
+
+/// The query string, e.g. a struct with a field per key; a key sent more
+/// than once fills a `Vec`. A query that doesn't fit `T` is answered with
+/// 400 Bad Request.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: serde::de::DeserializeOwned> FromRequest for Query<T> {
+  type Rejection = Rejection;
+
+  fn from_request(request: &Request) -> Result<Self, Rejection> {
+    let pairs = request.query().iter().map(|(k, v)| (k.as_str(), v.as_str()));
+    de::from_pairs(pairs)
+      .map(Query)
+      .map_err(|e| (StatusCode::BadRequest, format!("invalid query: {}", e)))
+  }
+}
+
+/// An `application/x-www-form-urlencoded` body, read like [`Query`]. Another
+/// content type is answered with 415 Unsupported Media Type and a form that
+/// doesn't fit `T` with 422 Unprocessable Entity.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Form<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: serde::de::DeserializeOwned> FromRequest for Form<T> {
+  type Rejection = Rejection;
+
+  fn from_request(request: &Request) -> Result<Self, Rejection> {
+    if !media_type_is(request, |t| t == "application/x-www-form-urlencoded") {
+      return Err((
+        StatusCode::UnsupportedMediaType,
+        "expected Content-Type application/x-www-form-urlencoded".to_string(),
+      ));
+    }
+    let Ok(body) = std::str::from_utf8(&request.body) else {
+      return Err((StatusCode::BadRequest, "request body is not valid UTF-8".to_string()));
+    };
+    let pairs = crate::core::utils::parse_urlencoded(body);
+    de::from_pairs(pairs.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+      .map(Form)
+      .map_err(|e| (StatusCode::UnprocessableEntity, format!("invalid form: {}", e)))
+  }
+}
+
+/// A JSON body. Another content type is answered with 415 Unsupported Media
+/// Type, malformed JSON with 400 Bad Request and JSON that doesn't fit `T`
+/// with 422 Unprocessable Entity.
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> FromRequest for crate::core::into_response::Json<T> {
+  type Rejection = Rejection;
+
+  fn from_request(request: &Request) -> Result<Self, Rejection> {
+    if !media_type_is(request, |t| t == "application/json" || t.ends_with("+json")) {
+      return Err((
+        StatusCode::UnsupportedMediaType,
+        "expected Content-Type application/json".to_string(),
+      ));
+    }
+    serde_json::from_slice(&request.body)
+      .map(crate::core::into_response::Json)
+      .map_err(|e| {
+        let status = if e.is_data() {
+          StatusCode::UnprocessableEntity
+        } else {
+          StatusCode::BadRequest
+        };
+        (status, format!("invalid JSON body: {}", e))
+      })
+  }
+}
+
+/// A serde deserializer over decoded string pairs, for path parameters,
+/// query strings and forms. Values are parsed into whatever type is asked
+/// for, so `id=7` fills a `u64` field.
+#[cfg(feature = "serde")]
+mod de {
+  use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+  use serde::de::{DeserializeOwned, Deserializer, Error as _, IntoDeserializer, Visitor};
+  use serde::forward_to_deserialize_any;
+
+  /// `T` from `pairs`, as a map or struct keyed by name. A key sent more
+  /// than once fills a sequence; when `T` isn't a map or struct the pairs
+  /// must hold a single key, whose value is used.
+  pub fn from_pairs<'p, T, I>(pairs: I) -> Result<T, Error>
+  where
+    T: DeserializeOwned,
+    I: IntoIterator<Item = (&'p str, &'p str)>,
+  {
+    let mut grouped: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (key, value) in pairs {
+      match grouped.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, values)) => values.push(value),
+        None => grouped.push((key, vec![value])),
+      }
+    }
+    T::deserialize(Pairs(grouped))
+  }
+
+  struct Pairs<'de>(Vec<(&'de str, Vec<&'de str>)>);
+
+  impl<'de> Pairs<'de> {
+    fn single(self) -> Result<Value<'de>, Error> {
+      match <[_; 1]>::try_from(self.0) {
+        Ok([(_, values)]) => Ok(Value(values)),
+        Err(pairs) => Err(Error::custom(format!("expected a single value, found {}", pairs.len()))),
+      }
+    }
+  }
+
+  macro_rules! from_single {
+    ($($method:ident),*) => {
+      $(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+          self.single()?.$method(visitor)
+        }
+      )*
+    };
+  }
+
+  impl<'de> Deserializer<'de> for Pairs<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      visitor.visit_map(MapDeserializer::new(self.0.into_iter().map(|(k, v)| (k, Value(v)))))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+      visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+      self.single()?.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+      self,
+      name: &'static str,
+      variants: &'static [&'static str],
+      visitor: V,
+    ) -> Result<V::Value, Error> {
+      self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    from_single!(
+      deserialize_bool,
+      deserialize_i8,
+      deserialize_i16,
+      deserialize_i32,
+      deserialize_i64,
+      deserialize_i128,
+      deserialize_u8,
+      deserialize_u16,
+      deserialize_u32,
+      deserialize_u64,
+      deserialize_u128,
+      deserialize_f32,
+      deserialize_f64,
+      deserialize_char,
+      deserialize_str,
+      deserialize_string,
+      deserialize_option,
+      deserialize_seq
+    );
+
+    forward_to_deserialize_any! {
+      bytes byte_buf unit unit_struct tuple_struct map struct identifier ignored_any
+    }
+  }
+
+  /// The values sent for one key, in order; scalars take the last one.
+  struct Value<'de>(Vec<&'de str>);
+
+  impl<'de> Value<'de> {
+    fn last(&self) -> &'de str {
+      self.0.last().copied().unwrap_or("")
+    }
+  }
+
+  impl<'de> IntoDeserializer<'de, Error> for Value<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+      self
+    }
+  }
+
+  macro_rules! parse_value {
+    ($($method:ident => $visit:ident: $ty:ty),*) => {
+      $(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+          let value = self.last();
+          match value.parse::<$ty>() {
+            Ok(parsed) => visitor.$visit(parsed),
+            Err(_) => Err(Error::custom(format!("cannot parse `{}` as {}", value, stringify!($ty)))),
+          }
+        }
+      )*
+    };
+  }
+
+  impl<'de> Deserializer<'de> for Value<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      visitor.visit_borrowed_str(self.last())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+      visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      visitor.visit_seq(SeqDeserializer::new(self.0.into_iter().map(|v| Value(vec![v]))))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+      self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+      self,
+      _: &'static str,
+      _: &'static [&'static str],
+      visitor: V,
+    ) -> Result<V::Value, Error> {
+      visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(self.last()))
+    }
+
+    parse_value!(
+      deserialize_bool => visit_bool: bool,
+      deserialize_i8 => visit_i8: i8,
+      deserialize_i16 => visit_i16: i16,
+      deserialize_i32 => visit_i32: i32,
+      deserialize_i64 => visit_i64: i64,
+      deserialize_i128 => visit_i128: i128,
+      deserialize_u8 => visit_u8: u8,
+      deserialize_u16 => visit_u16: u16,
+      deserialize_u32 => visit_u32: u32,
+      deserialize_u64 => visit_u64: u64,
+      deserialize_u128 => visit_u128: u128,
+      deserialize_f32 => visit_f32: f32,
+      deserialize_f64 => visit_f64: f64,
+      deserialize_char => visit_char: char
+    );
+
+    forward_to_deserialize_any! {
+      str string bytes byte_buf unit unit_struct tuple_struct map struct identifier ignored_any
+    }
+  }
+}
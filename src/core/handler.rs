@@ -7,9 +7,12 @@
   feature = "async_smol"
 ))]
 
+use crate::core::extract::FromRequest;
+use crate::core::into_response::IntoResponse;
 use crate::{Request, Response};
 use async_trait::async_trait;
 use futures::future::BoxFuture;
+use std::marker::PhantomData;
 use std::sync::Arc;
 
 /// The core, unified `Handler` trait, powered by `async-trait`.
@@ -32,19 +35,22 @@ impl Handler for Arc<dyn Handler> {
 struct SyncFnHandler<F>(F);
 
 #[async_trait]
-impl<F> Handler for SyncFnHandler<F>
+impl<F, R> Handler for SyncFnHandler<F>
 where
-  F: for<'a> Fn(&'a Request) -> Response + Send + Sync,
+  F: for<'a> Fn(&'a Request) -> R + Send + Sync,
+  R: IntoResponse,
 {
   async fn handle(&self, request: &Request) -> Response {
-    (self.0)(request)
+    (self.0)(request).into_response()
   }
 }
 
 /// Wraps a synchronous function, turning it into a type that implements `Handler`.
-pub fn sync_h<F>(f: F) -> Arc<dyn Handler>
+/// The function can return anything implementing [`IntoResponse`].
+pub fn sync_h<F, R>(f: F) -> Arc<dyn Handler>
 where
-  F: for<'a> Fn(&'a Request) -> Response + Send + Sync + 'static,
+  F: for<'a> Fn(&'a Request) -> R + Send + Sync + 'static,
+  R: IntoResponse,
 {
   Arc::new(SyncFnHandler(f))
 }
@@ -70,32 +76,209 @@ where
     Arc::new(AsyncFnHandler(f))
 }
 
+// --- Handlers taking extractors ---
+
+/// Marks functions taking the whole `&Request` among the argument lists
+/// `handler!` accepts; extractor lists are marked by their tuple of types.
+pub struct ByRequest;
+
+/// A synchronous function `handler!` can wrap: one taking `&Request`, or one
+/// taking up to eight [`FromRequest`] extractors. `Args` tells them apart.
+pub trait SyncHandlerFn<Args>: Send + Sync + 'static {
+  fn call(&self, request: &Request) -> Response;
+}
+
+impl<F, R> SyncHandlerFn<ByRequest> for F
+where
+  F: for<'a> Fn(&'a Request) -> R + Send + Sync + 'static,
+  R: IntoResponse,
+{
+  fn call(&self, request: &Request) -> Response {
+    self(request).into_response()
+  }
+}
+
+/// An asynchronous function `handler!` can wrap: an `async fn` taking
+/// `&Request`, or one taking up to eight [`FromRequest`] extractors.
+pub trait AsyncHandlerFn<Args>: Send + Sync + 'static {
+  fn call<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Response>;
+}
+
+/// An `async fn` taking `&'a Request`, naming the future it returns.
+pub trait RequestFn<'a>: Send + Sync {
+  type Output: IntoResponse;
+  type Future: Future<Output = Self::Output> + Send + 'a;
+
+  fn call(&self, request: &'a Request) -> Self::Future;
+}
+
+impl<'a, F, Fut> RequestFn<'a> for F
+where
+  F: Fn(&'a Request) -> Fut + Send + Sync,
+  Fut: Future + Send + 'a,
+  Fut::Output: IntoResponse,
+{
+  type Output = Fut::Output;
+  type Future = Fut;
+
+  fn call(&self, request: &'a Request) -> Fut {
+    self(request)
+  }
+}
+
+impl<F> AsyncHandlerFn<ByRequest> for F
+where
+  F: for<'a> RequestFn<'a> + 'static,
+{
+  fn call<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Response> {
+    let future = RequestFn::call(self, request);
+    Box::pin(async move { future.await.into_response() })
+  }
+}
+
+/// An extractor argument list, built from the request in order; the first
+/// extractor refusing the request gives the response.
+trait ExtractArgs: Sized {
+  fn extract(request: &Request) -> Result<Self, Response>;
+}
+
+macro_rules! extractor_fns {
+  ($($arg:ident),*) => {
+    impl<$($arg: FromRequest),*> ExtractArgs for ($($arg,)*) {
+      #[allow(unused_variables)]
+      fn extract(request: &Request) -> Result<Self, Response> {
+        Ok(($($arg::from_request(request).map_err(IntoResponse::into_response)?,)*))
+      }
+    }
+
+    impl<F, R, $($arg),*> SyncHandlerFn<($($arg,)*)> for F
+    where
+      F: Fn($($arg),*) -> R + Send + Sync + 'static,
+      R: IntoResponse,
+      $($arg: FromRequest,)*
+    {
+      #[allow(non_snake_case)]
+      fn call(&self, request: &Request) -> Response {
+        match <($($arg,)*)>::extract(request) {
+          Ok(($($arg,)*)) => self($($arg),*).into_response(),
+          Err(rejection) => rejection,
+        }
+      }
+    }
+
+    impl<F, Fut, $($arg),*> AsyncHandlerFn<($($arg,)*)> for F
+    where
+      F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+      Fut: Future + Send + 'static,
+      Fut::Output: IntoResponse,
+      $($arg: FromRequest,)*
+    {
+      #[allow(non_snake_case)]
+      fn call<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Response> {
+        match <($($arg,)*)>::extract(request) {
+          Ok(($($arg,)*)) => {
+            let future = self($($arg),*);
+            Box::pin(async move { future.await.into_response() })
+          }
+          Err(rejection) => Box::pin(std::future::ready(rejection)),
+        }
+      }
+    }
+  };
+}
+
+extractor_fns!();
+extractor_fns!(T1);
+extractor_fns!(T1, T2);
+extractor_fns!(T1, T2, T3);
+extractor_fns!(T1, T2, T3, T4);
+extractor_fns!(T1, T2, T3, T4, T5);
+extractor_fns!(T1, T2, T3, T4, T5, T6);
+extractor_fns!(T1, T2, T3, T4, T5, T6, T7);
+extractor_fns!(T1, T2, T3, T4, T5, T6, T7, T8);
+
+struct SyncExtractHandler<F, Args>(F, PhantomData<fn() -> Args>);
+
+#[async_trait]
+impl<F, Args> Handler for SyncExtractHandler<F, Args>
+where
+  F: SyncHandlerFn<Args>,
+{
+  async fn handle(&self, request: &Request) -> Response {
+    self.0.call(request)
+  }
+}
+
+/// Wraps a synchronous function taking `&Request` or extractors.
+pub fn sync_extract_h<F, Args>(f: F) -> Arc<dyn Handler>
+where
+  F: SyncHandlerFn<Args>,
+  Args: 'static,
+{
+  Arc::new(SyncExtractHandler(f, PhantomData))
+}
+
+struct AsyncExtractHandler<F, Args>(F, PhantomData<fn() -> Args>);
+
+#[async_trait]
+impl<F, Args> Handler for AsyncExtractHandler<F, Args>
+where
+  F: AsyncHandlerFn<Args>,
+{
+  async fn handle(&self, request: &Request) -> Response {
+    self.0.call(request).await
+  }
+}
+
+/// Wraps an `async fn` taking `&Request` or extractors.
+pub fn async_extract_h<F, Args>(f: F) -> Arc<dyn Handler>
+where
+  F: AsyncHandlerFn<Args>,
+  Args: 'static,
+{
+  Arc::new(AsyncExtractHandler(f, PhantomData))
+}
+
 /// Simplifies handler creation for synchronous builds.
 ///
-/// This macro expands to a call to the `sync_h` helper function,
+/// This macro expands to a call to the `sync_extract_h` helper function,
 /// which wraps the synchronous handler function to make it compatible
-/// with the server's unified handler system.
+/// with the server's unified handler system. The function takes either
+/// `&Request` or any number of [`FromRequest`] extractors. A closure with a
+/// single untyped argument, like `|req| ...`, is given the `&Request`.
 #[macro_export]
 #[cfg(feature = "sync")]
 macro_rules! handler {
+    (|$req:pat_param| $body:expr) => {
+        $crate::core::handler::sync_h(|$req| $body)
+    };
     ($handler_fn:expr) => {
-        $crate::core::handler::sync_h($handler_fn)
+        $crate::core::handler::sync_extract_h($handler_fn)
     };
 }
 
 /// Simplifies handler creation for asynchronous builds.
 ///
-/// This macro expands to a call to the `async_h` helper function,
-/// wrapping the user's `async fn` in a closure that pins and boxes the
-/// future. This hides the necessary boilerplate from the user, providing
-/// a clean API.
+/// This macro expands to a call to the `async_extract_h` helper function,
+/// which pins and boxes the future of the user's `async fn`. This hides
+/// the necessary boilerplate from the user, providing a clean API. The
+/// function takes either `&Request` or any number of [`FromRequest`]
+/// extractors, and can return anything implementing [`IntoResponse`]. A
+/// closure with a single untyped argument, like `|req| async move { ... }`,
+/// is given the `&Request`.
 #[macro_export]
 #[cfg(all(
   any(feature = "async_tokio", feature = "async_std", feature = "async_smol"),
   not(feature = "sync")
 ))]
 macro_rules! handler {
+    (|$req:pat_param| $body:expr) => {
+        $crate::core::handler::async_h(move |$req| {
+            let future = $body;
+            Box::pin(async move { $crate::core::into_response::IntoResponse::into_response(future.await) })
+        })
+    };
     ($handler_fn:expr) => {
-        $crate::core::handler::async_h(move |req| Box::pin($handler_fn(req)))
+        $crate::core::handler::async_extract_h($handler_fn)
     };
 }
@@ -0,0 +1,138 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::core::request_type::is_token;
+
+/// An ordered, multi-valued collection of HTTP header fields.
+///
+/// Field names keep the casing they were given with, but every lookup is
+/// case-insensitive as required by RFC 9110.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Headers {
+  entries: Vec<(String, String)>,
+}
+
+impl Headers {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds a field without touching existing ones with the same name.
+  pub fn append<N, V>(&mut self, name: N, value: V)
+  where
+    N: Into<String>,
+    V: Into<String>,
+  {
+    self.entries.push((name.into(), value.into()));
+  }
+
+  /// Sets a field, replacing every previous value with the same name.
+  pub fn insert<N, V>(&mut self, name: N, value: V)
+  where
+    N: Into<String>,
+    V: Into<String>,
+  {
+    let name = name.into();
+    self.remove(&name);
+    self.entries.push((name, value.into()));
+  }
+
+  /// Returns the first value of the given field.
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self
+      .entries
+      .iter()
+      .find(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  }
+
+  /// Returns every value of the given field, in insertion order.
+  pub fn get_all(&self, name: &str) -> Vec<&str> {
+    self
+      .entries
+      .iter()
+      .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+      .collect()
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.entries.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))
+  }
+
+  /// Removes every value of the given field.
+  pub fn remove(&mut self, name: &str) {
+    self.entries.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+  }
+
+  /// `Content-Length` parsed as a number, `None` when absent or malformed.
+  pub fn content_length(&self) -> Option<usize> {
+    self.get("Content-Length")?.trim().parse().ok()
+  }
+
+  pub fn content_type(&self) -> Option<&str> {
+    self.get("Content-Type")
+  }
+
+  pub fn host(&self) -> Option<&str> {
+    self.get("Host")
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Parses a block of `name: value` lines as sent on the wire.
+  ///
+  /// Whitespace around the value is trimmed. Returns `None` for a line
+  /// without a colon, a name that is not a valid token (which includes
+  /// whitespace before the colon), a value holding a control character other
+  /// than tab, or an obsolete folded continuation line.
+  pub fn parse<'a, I>(lines: I) -> Option<Self>
+  where
+    I: IntoIterator<Item = &'a str>,
+  {
+    let mut headers = Headers::new();
+    for line in lines {
+      if line.starts_with([' ', '\t']) {
+        return None;
+      }
+      let (name, value) = line.split_once(':')?;
+      if !is_token(name) || value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+        return None;
+      }
+      headers.append(name, value.trim_matches([' ', '\t']));
+    }
+    Some(headers)
+  }
+}
+
+impl<N, V> FromIterator<(N, V)> for Headers
+where
+  N: Into<String>,
+  V: Into<String>,
+{
+  fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> Self {
+    let mut headers = Headers::new();
+    for (name, value) in iter {
+      headers.append(name, value);
+    }
+    headers
+  }
+}
+
+impl Display for Headers {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    for (name, value) in self.iter() {
+      write!(f, "{}: {}\r\n", name, value)?;
+    }
+    Ok(())
+  }
+}
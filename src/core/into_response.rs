@@ -0,0 +1,111 @@
+use crate::core::body::Body;
+use crate::core::response::Response;
+use crate::core::status_code::StatusCode;
+
+/// Anything a handler can return.
+///
+/// Text is sent as `text/plain`, bytes as `application/octet-stream`, both
+/// with 200 OK. `(StatusCode, T)` answers `T` with another status, `None`
+/// is a 404 and `Result` answers with whichever side it holds.
+pub trait IntoResponse {
+  fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+  fn into_response(self) -> Response {
+    self
+  }
+}
+
+impl IntoResponse for String {
+  fn into_response(self) -> Response {
+    Response::builder().content_type("text/plain").body(self)
+  }
+}
+
+impl IntoResponse for &'static str {
+  fn into_response(self) -> Response {
+    Response::builder().content_type("text/plain").body(self)
+  }
+}
+
+impl IntoResponse for Vec<u8> {
+  fn into_response(self) -> Response {
+    Response::builder().content_type("application/octet-stream").body(self)
+  }
+}
+
+impl IntoResponse for &'static [u8] {
+  fn into_response(self) -> Response {
+    Response::builder().content_type("application/octet-stream").body(self)
+  }
+}
+
+impl IntoResponse for Body {
+  fn into_response(self) -> Response {
+    Response::builder().content_type("application/octet-stream").body(self)
+  }
+}
+
+/// An empty answer with the status.
+impl IntoResponse for StatusCode {
+  fn into_response(self) -> Response {
+    Response::builder().status(self).body(Vec::new())
+  }
+}
+
+impl<T: IntoResponse> IntoResponse for (StatusCode, T) {
+  fn into_response(self) -> Response {
+    let mut response = self.1.into_response();
+    response.status = self.0.to_string();
+    response
+  }
+}
+
+/// `None` is answered with 404 Not Found.
+impl<T: IntoResponse> IntoResponse for Option<T> {
+  fn into_response(self) -> Response {
+    match self {
+      Some(value) => value.into_response(),
+      None => Response::new(),
+    }
+  }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+  fn into_response(self) -> Response {
+    match self {
+      Ok(value) => value.into_response(),
+      Err(error) => error.into_response(),
+    }
+  }
+}
+
+/// Never built; lets extractors that can't fail name a rejection.
+impl IntoResponse for std::convert::Infallible {
+  fn into_response(self) -> Response {
+    match self {}
+  }
+}
+
+/// A value sent as `application/json`. A value that fails to serialize is
+/// answered with 500 Internal Server Error.
+///
+/// Taken as a handler argument it reads a JSON request body; see
+/// [`FromRequest`](crate::FromRequest).
+#[cfg(feature = "json")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize> IntoResponse for Json<T> {
+  fn into_response(self) -> Response {
+    match serde_json::to_vec(&self.0) {
+      Ok(bytes) => Response::builder().content_type("application/json").body(bytes),
+      Err(error) => Response::builder()
+        .status(StatusCode::InternalServerError)
+        .content_type("text/plain")
+        .body(error.to_string()),
+    }
+  }
+}
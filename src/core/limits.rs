@@ -0,0 +1,25 @@
+/// Bounds on how much of a request the server is willing to buffer.
+///
+/// A request whose head is larger than `max_header_bytes` or has more than
+/// `max_headers` fields is answered with 431 Request Header Fields Too Large;
+/// one whose body is larger than `max_body_bytes` with 413 Payload Too Large.
+/// Both are rejected before the offending part is read into memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+  /// Request line plus header block, in bytes.
+  pub max_header_bytes: usize,
+  /// Number of header fields, trailers of a chunked body included.
+  pub max_headers: usize,
+  /// Decoded body, in bytes. Routes can override it with their own limit.
+  pub max_body_bytes: usize,
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Limits {
+      max_header_bytes: 16 * 1024,
+      max_headers: 100,
+      max_body_bytes: 2 * 1024 * 1024,
+    }
+  }
+}
@@ -0,0 +1,126 @@
+#![cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+
+use crate::core::handler::Handler;
+use crate::{Request, Response};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+/// Code run around handlers, on every runtime alike.
+///
+/// A middleware gets the request before the handler does and decides what
+/// happens next: it can change the request, answer it itself without calling
+/// `next` (short-circuit), or call `next.run(request)` and change the
+/// response on its way out. Middlewares run in the order they were added:
+/// server-wide ones first, then the ones of the route's groups, then the
+/// route's own.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+  async fn handle(&self, request: &mut Request, next: Next<'_>) -> Response;
+}
+
+/// The rest of a middleware chain, ending with the handler.
+pub struct Next<'a> {
+  chain: &'a [Arc<dyn Middleware>],
+  endpoint: &'a dyn Endpoint,
+}
+
+impl<'a> Next<'a> {
+  pub(crate) fn new(chain: &'a [Arc<dyn Middleware>], endpoint: &'a dyn Endpoint) -> Self {
+    Next { chain, endpoint }
+  }
+
+  /// Passes the request on to the next middleware, or to the handler.
+  pub async fn run(self, request: &mut Request) -> Response {
+    match self.chain.split_first() {
+      Some((first, rest)) => first.handle(request, Next::new(rest, self.endpoint)).await,
+      None => self.endpoint.call(request).await,
+    }
+  }
+}
+
+/// What a chain ends with: a route's handler, or the whole dispatch.
+#[async_trait]
+pub(crate) trait Endpoint: Send + Sync {
+  async fn call(&self, request: &mut Request) -> Response;
+}
+
+#[async_trait]
+impl Endpoint for Arc<dyn Handler> {
+  async fn call(&self, request: &mut Request) -> Response {
+    self.handle(request).await
+  }
+}
+
+// --- Helper Functions and Structs ---
+
+struct Before<F>(F);
+
+#[async_trait]
+impl<F> Middleware for Before<F>
+where
+  F: Fn(&mut Request) -> Option<Response> + Send + Sync,
+{
+  async fn handle(&self, request: &mut Request, next: Next<'_>) -> Response {
+    match (self.0)(request) {
+      Some(response) => response,
+      None => next.run(request).await,
+    }
+  }
+}
+
+/// A middleware running `f` before the handler. Returning a response from
+/// `f` answers the request without calling the handler.
+pub fn before<F>(f: F) -> Arc<dyn Middleware>
+where
+  F: Fn(&mut Request) -> Option<Response> + Send + Sync + 'static,
+{
+  Arc::new(Before(f))
+}
+
+struct After<F>(F);
+
+#[async_trait]
+impl<F> Middleware for After<F>
+where
+  F: Fn(&Request, Response) -> Response + Send + Sync,
+{
+  async fn handle(&self, request: &mut Request, next: Next<'_>) -> Response {
+    let response = next.run(request).await;
+    (self.0)(request, response)
+  }
+}
+
+/// A middleware passing the handler's response through `f`.
+pub fn after<F>(f: F) -> Arc<dyn Middleware>
+where
+  F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+{
+  Arc::new(After(f))
+}
+
+struct Around<F>(F);
+
+#[async_trait]
+impl<F> Middleware for Around<F>
+where
+  F: for<'a> Fn(&'a mut Request, Next<'a>) -> BoxFuture<'a, Response> + Send + Sync,
+{
+  async fn handle(&self, request: &mut Request, next: Next<'_>) -> Response {
+    (self.0)(request, next).await
+  }
+}
+
+/// A middleware from a closure returning a boxed future, e.g.
+/// `around(|req, next| Box::pin(async move { next.run(req).await }))`.
+pub fn around<F>(f: F) -> Arc<dyn Middleware>
+where
+  F: for<'a> Fn(&'a mut Request, Next<'a>) -> BoxFuture<'a, Response> + Send + Sync + 'static,
+{
+  Arc::new(Around(f))
+}
@@ -1,8 +1,20 @@
+pub mod body;
+pub mod error_pages;
+pub mod extract;
 pub mod handler;
+pub mod headers;
+pub mod into_response;
+pub mod limits;
+pub mod middleware;
+pub mod param;
 pub mod request;
 pub mod request_handler;
 pub mod request_type;
 pub mod response;
+pub mod route_tree;
+pub mod router;
+pub mod server_config;
 pub mod status_code;
 pub mod test_utils;
+pub mod timeouts;
 pub mod utils;
@@ -0,0 +1,316 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// A restriction on the values a `{name:constraint}` route segment accepts.
+///
+/// The constraint is either one of the built-in type names (`u8`..`u128`,
+/// `i8`..`i128`, `usize`, `isize`, `f32`, `f64`, `bool`, `uuid`, `alpha`,
+/// `alnum`) or a small anchored pattern such as `[a-z-]+` or `\d{4}`.
+#[derive(Debug)]
+pub enum Constraint {
+  Type(ParamType),
+  Pattern(Pattern),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamType {
+  U8,
+  U16,
+  U32,
+  U64,
+  U128,
+  Usize,
+  I8,
+  I16,
+  I32,
+  I64,
+  I128,
+  Isize,
+  F32,
+  F64,
+  Bool,
+  Uuid,
+  Alpha,
+  Alnum,
+}
+
+impl Constraint {
+  /// Parses the text after the `:` of a route segment.
+  pub fn parse(source: &str) -> Result<Self, String> {
+    let ty = match source {
+      "u8" => ParamType::U8,
+      "u16" => ParamType::U16,
+      "u32" => ParamType::U32,
+      "u64" => ParamType::U64,
+      "u128" => ParamType::U128,
+      "usize" => ParamType::Usize,
+      "i8" => ParamType::I8,
+      "i16" => ParamType::I16,
+      "i32" => ParamType::I32,
+      "i64" => ParamType::I64,
+      "i128" => ParamType::I128,
+      "isize" => ParamType::Isize,
+      "f32" => ParamType::F32,
+      "f64" => ParamType::F64,
+      "bool" => ParamType::Bool,
+      "uuid" => ParamType::Uuid,
+      "alpha" => ParamType::Alpha,
+      "alnum" => ParamType::Alnum,
+      _ => return Pattern::parse(source).map(Constraint::Pattern),
+    };
+    Ok(Constraint::Type(ty))
+  }
+
+  pub fn matches(&self, value: &str) -> bool {
+    match self {
+      Constraint::Type(ty) => ty.matches(value),
+      Constraint::Pattern(pattern) => pattern.matches(value),
+    }
+  }
+}
+
+impl ParamType {
+  pub fn matches(&self, value: &str) -> bool {
+    match self {
+      ParamType::U8 => value.parse::<u8>().is_ok(),
+      ParamType::U16 => value.parse::<u16>().is_ok(),
+      ParamType::U32 => value.parse::<u32>().is_ok(),
+      ParamType::U64 => value.parse::<u64>().is_ok(),
+      ParamType::U128 => value.parse::<u128>().is_ok(),
+      ParamType::Usize => value.parse::<usize>().is_ok(),
+      ParamType::I8 => value.parse::<i8>().is_ok(),
+      ParamType::I16 => value.parse::<i16>().is_ok(),
+      ParamType::I32 => value.parse::<i32>().is_ok(),
+      ParamType::I64 => value.parse::<i64>().is_ok(),
+      ParamType::I128 => value.parse::<i128>().is_ok(),
+      ParamType::Isize => value.parse::<isize>().is_ok(),
+      ParamType::F32 => value.parse::<f32>().is_ok(),
+      ParamType::F64 => value.parse::<f64>().is_ok(),
+      ParamType::Bool => value.parse::<bool>().is_ok(),
+      ParamType::Uuid => is_uuid(value),
+      ParamType::Alpha => !value.is_empty() && value.chars().all(|c| c.is_ascii_alphabetic()),
+      ParamType::Alnum => !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric()),
+    }
+  }
+}
+
+/// Accepts the canonical 8-4-4-4-12 hexadecimal form.
+fn is_uuid(value: &str) -> bool {
+  let groups: Vec<&str> = value.split('-').collect();
+  let lengths = [8, 4, 4, 4, 12];
+  groups.len() == lengths.len()
+    && groups
+      .iter()
+      .zip(lengths)
+      .all(|(g, len)| g.len() == len && g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// A minimal, always anchored pattern: a sequence of literals, `.`, `\d`,
+/// `\w`, `\s` or `[...]` classes, each optionally followed by `?`, `*`, `+`,
+/// `{n}`, `{n,}` or `{n,m}`. Groups and alternation are not supported.
+#[derive(Debug)]
+pub struct Pattern {
+  pieces: Vec<Piece>,
+}
+
+#[derive(Debug)]
+struct Piece {
+  atom: Atom,
+  min: usize,
+  max: usize,
+}
+
+#[derive(Debug)]
+enum Atom {
+  Literal(char),
+  Any,
+  Class { negated: bool, items: Vec<ClassItem> },
+}
+
+#[derive(Debug)]
+enum ClassItem {
+  Char(char),
+  Range(char, char),
+  Digit,
+  Word,
+  Space,
+}
+
+impl ClassItem {
+  fn matches(&self, c: char) -> bool {
+    match self {
+      ClassItem::Char(x) => *x == c,
+      ClassItem::Range(a, b) => *a <= c && c <= *b,
+      ClassItem::Digit => c.is_ascii_digit(),
+      ClassItem::Word => c.is_ascii_alphanumeric() || c == '_',
+      ClassItem::Space => c.is_whitespace(),
+    }
+  }
+}
+
+impl Atom {
+  fn matches(&self, c: char) -> bool {
+    match self {
+      Atom::Literal(x) => *x == c,
+      Atom::Any => true,
+      Atom::Class { negated, items } => items.iter().any(|i| i.matches(c)) != *negated,
+    }
+  }
+}
+
+fn escaped_item(c: char) -> ClassItem {
+  match c {
+    'd' => ClassItem::Digit,
+    'w' => ClassItem::Word,
+    's' => ClassItem::Space,
+    other => ClassItem::Char(other),
+  }
+}
+
+impl Pattern {
+  pub fn parse(source: &str) -> Result<Self, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+      let atom = match chars[i] {
+        '\\' => {
+          i += 1;
+          let c = *chars.get(i).ok_or("dangling escape")?;
+          match escaped_item(c) {
+            ClassItem::Char(c) => Atom::Literal(c),
+            item => Atom::Class {
+              negated: false,
+              items: vec![item],
+            },
+          }
+        }
+        '.' => Atom::Any,
+        '[' => {
+          i += 1;
+          let negated = chars.get(i) == Some(&'^');
+          if negated {
+            i += 1;
+          }
+          let mut items = Vec::new();
+          loop {
+            let c = *chars.get(i).ok_or("unterminated character class")?;
+            if c == ']' && !items.is_empty() {
+              break;
+            }
+            let item = if c == '\\' {
+              i += 1;
+              escaped_item(*chars.get(i).ok_or("dangling escape")?)
+            } else if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|e| *e != ']') {
+              let end = chars[i + 2];
+              if end < c {
+                return Err(format!("invalid range {}-{}", c, end));
+              }
+              i += 2;
+              ClassItem::Range(c, end)
+            } else {
+              ClassItem::Char(c)
+            };
+            items.push(item);
+            i += 1;
+          }
+          Atom::Class { negated, items }
+        }
+        c @ ('*' | '+' | '?' | '{' | '(' | ')' | '|') => {
+          return Err(format!("unexpected `{}` at position {}", c, i));
+        }
+        c => Atom::Literal(c),
+      };
+      i += 1;
+      let (min, max, consumed) = match chars.get(i) {
+        Some('?') => (0, 1, 1),
+        Some('*') => (0, usize::MAX, 1),
+        Some('+') => (1, usize::MAX, 1),
+        Some('{') => {
+          let close = chars[i..]
+            .iter()
+            .position(|c| *c == '}')
+            .ok_or("unterminated repetition")?;
+          let body: String = chars[i + 1..i + close].iter().collect();
+          let bad = || format!("invalid repetition {{{}}}", body);
+          let (lo, hi) = match body.split_once(',') {
+            None => {
+              let n = body.parse().map_err(|_| bad())?;
+              (n, n)
+            }
+            Some((lo, "")) => (lo.parse().map_err(|_| bad())?, usize::MAX),
+            Some((lo, hi)) => (lo.parse().map_err(|_| bad())?, hi.parse().map_err(|_| bad())?),
+          };
+          if lo > hi {
+            return Err(bad());
+          }
+          (lo, hi, close + 1)
+        }
+        _ => (1, 1, 0),
+      };
+      i += consumed;
+      pieces.push(Piece { atom, min, max });
+    }
+    Ok(Pattern { pieces })
+  }
+
+  /// Returns `true` when the whole `value` matches the pattern.
+  pub fn matches(&self, value: &str) -> bool {
+    let chars: Vec<char> = value.chars().collect();
+    Self::match_from(&self.pieces, &chars)
+  }
+
+  /// Tracks every input offset the pieces so far can end at, one piece at a
+  /// time, so matching stays linear in the input for each piece instead of
+  /// backtracking through every way of splitting it.
+  fn match_from(pieces: &[Piece], input: &[char]) -> bool {
+    let len = input.len();
+    let mut reachable = vec![false; len + 1];
+    reachable[0] = true;
+    for piece in pieces {
+      // run[p]: how many characters from `p` on the atom matches in a row.
+      let mut run = vec![0; len + 1];
+      for p in (0..len).rev() {
+        if piece.atom.matches(input[p]) {
+          run[p] = run[p + 1] + 1;
+        }
+      }
+      // Each reachable offset opens a range of end offsets; mark them with a
+      // difference array.
+      let mut delta = vec![0isize; len + 2];
+      for p in (0..=len).filter(|p| reachable[*p]) {
+        let most = run[p].min(piece.max);
+        if most >= piece.min {
+          delta[p + piece.min] += 1;
+          delta[p + most + 1] -= 1;
+        }
+      }
+      let mut open = 0;
+      for (p, slot) in reachable.iter_mut().enumerate() {
+        open += delta[p];
+        *slot = open > 0;
+      }
+    }
+    reachable[len]
+  }
+}
+
+/// Why a typed path parameter could not be read from a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamError {
+  /// The route captured no parameter with that name.
+  Missing(String),
+  /// The captured value does not parse as the requested type.
+  Invalid { name: String, value: String },
+}
+
+impl Display for ParamError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      ParamError::Missing(name) => write!(f, "missing parameter `{}`", name),
+      ParamError::Invalid { name, value } => write!(f, "invalid value `{}` for parameter `{}`", value, name),
+    }
+  }
+}
+
+impl Error for ParamError {}
@@ -2,72 +2,145 @@
 ///
 /// This macro abstracts the common logic of reading and parsing an HTTP request
 /// from a TCP stream, while allowing the caller to specify the runtime-specific
-/// types and traits (stream type, BufReader, and I/O extension traits).
+/// types and traits (buffered stream type and I/O extension traits).
 macro_rules! create_async_parse_stream {
     (
         $(#[$outer:meta])*
         $func_name:ident,
-        $stream_ty:ty,
         $buf_reader:ty,
         $async_read_ext:path,
-        $async_buf_read_ext:path
+        $async_buf_read_ext:path,
+        $sleep:path
     ) => {
         $(#[$outer])*
+        ///
+        /// The reader is owned by the connection so that bytes buffered past
+        /// the end of this request (a pipelined follow-up) are not lost.
+        /// Nothing beyond `limits` is read, and reading the head and the body
+        /// is bounded by `timeouts`. The request is not routed.
         pub async fn $func_name(
-            stream: &mut $stream_ty,
-            routes: &std::collections::HashMap<(crate::core::request_type::Rt, String), crate::core::request_handler::Rh>,
-            file_bases: &[String],
+            reader: &mut $buf_reader,
+            routes: &crate::core::route_tree::RouteTree,
+            limits: &crate::core::limits::Limits,
+            timeouts: &crate::core::timeouts::Timeouts,
         ) -> (crate::core::request::Request, Option<crate::core::response::Response>) {
             use $async_read_ext;
             use $async_buf_read_ext;
+            use crate::core::request::{chunk_size, head_line, Framing, Request, MAX_CHUNK_LINE};
+            use crate::core::status_code::StatusCode;
+            use crate::core::utils::within;
 
-            let mut reader = <$buf_reader>::new(stream);
             let mut raw = String::new();
 
-            // Read headers only
-            loop {
-                let mut line = String::new();
-                if reader.read_line(&mut line).await.ok().filter(|&n| n > 0).is_none() {
-                    break;
-                }
-                raw.push_str(&line);
-                if raw.contains("\r\n\r\n") {
-                    break;
-                }
+            // Read headers only, within the limits
+            let head = within(
+                async {
+                    let mut lines = 0;
+                    loop {
+                        let budget = limits.max_header_bytes.saturating_sub(raw.len()) as u64;
+                        let mut line = Vec::new();
+                        match (&mut *reader).take(budget).read_until(b'\n', &mut line).await {
+                            Ok(0) => break,
+                            Ok(_) => {}
+                            Err(_) => return Err(StatusCode::BadRequest),
+                        }
+                        raw.push_str(&head_line(line));
+                        lines += 1;
+                        if raw.contains("\r\n\r\n") {
+                            break;
+                        }
+                        if lines > limits.max_headers + 1 {
+                            return Err(StatusCode::RequestHeaderFieldsTooLarge);
+                        }
+                    }
+                    if raw.len() >= limits.max_header_bytes && !raw.contains("\r\n\r\n") {
+                        return Err(StatusCode::RequestHeaderFieldsTooLarge);
+                    }
+                    Ok(())
+                },
+                timeouts.header_read.map($sleep),
+            )
+            .await;
+            match head {
+                Some(Ok(())) => {}
+                Some(Err(status)) => return Request::reject(status),
+                None => return Request::reject(StatusCode::RequestTimeout),
             }
 
-            // Extract method from the first line
-            let method = raw
-                .lines()
-                .next()
-                .and_then(|l| l.split_whitespace().next())
-                .unwrap_or("");
-
-            // Determine content length
-            let content_length = raw
-                .lines()
-                .find_map(|l| {
-                    if l.to_ascii_lowercase().starts_with("content-length:") {
-                        l.split(':').nth(1)?.trim().parse::<usize>().ok()
-                    } else {
-                        None
+            let head_len = raw.len();
+            let max_body = Request::body_limit(&raw, routes, limits);
+            let framing = Framing::of(&raw);
+            let body: Option<Result<(Vec<u8>, String), StatusCode>> = within(
+                async {
+                    let bad = |_| StatusCode::BadRequest;
+                    match framing {
+                        Framing::Empty => Ok((Vec::new(), String::new())),
+                        Framing::Length(length) if length > max_body => Err(StatusCode::PayloadTooLarge),
+                        Framing::Length(length) => {
+                            let mut body = vec![0; length];
+                            reader.read_exact(&mut body).await.map_err(bad)?;
+                            Ok((body, String::new()))
+                        }
+                        Framing::Chunked => {
+                            // Same decoding as `Request::read_chunked_sync`
+                            let mut body = Vec::new();
+                            loop {
+                                let mut line = String::new();
+                                (&mut *reader).take(MAX_CHUNK_LINE).read_line(&mut line).await.map_err(bad)?;
+                                let size = chunk_size(&line).ok_or(StatusCode::BadRequest)?;
+                                if size == 0 {
+                                    break;
+                                }
+                                if size > max_body - body.len() {
+                                    return Err(StatusCode::PayloadTooLarge);
+                                }
+                                let read = (&mut *reader).take(size as u64).read_to_end(&mut body).await.map_err(bad)?;
+                                let mut crlf = [0; 2];
+                                reader.read_exact(&mut crlf).await.map_err(bad)?;
+                                if read != size || &crlf != b"\r\n" {
+                                    return Err(StatusCode::BadRequest);
+                                }
+                            }
+
+                            // Trailer fields up to the closing empty line
+                            let mut trailers = String::new();
+                            let mut count = 0;
+                            loop {
+                                let budget = limits.max_header_bytes.saturating_sub(head_len + trailers.len()) as u64;
+                                let mut line = String::new();
+                                if (&mut *reader).take(budget).read_line(&mut line).await.map_err(bad)? == 0 {
+                                    return Err(if budget == 0 {
+                                        StatusCode::RequestHeaderFieldsTooLarge
+                                    } else {
+                                        StatusCode::BadRequest
+                                    });
+                                }
+                                if line == "\r\n" {
+                                    return Ok((body, trailers));
+                                }
+                                trailers.push_str(&line);
+                                count += 1;
+                                if count > limits.max_headers {
+                                    return Err(StatusCode::RequestHeaderFieldsTooLarge);
+                                }
+                            }
+                        }
+                        Framing::Reject(status) => Err(status),
                     }
-                })
-                .unwrap_or(0);
-
-            if content_length > 0 {
-                // Read exactly content_length bytes
-                let mut buf = vec![0; content_length];
-                let _ = reader.read_exact(&mut buf).await;
-                raw.push_str(&String::from_utf8_lossy(&buf));
-            } else if method != "GET" {
-                // Read all until EOF for POST/PUT/DELETE without Content-Length
-                let mut rest = String::new();
-                let _ = reader.read_to_string(&mut rest).await;
-                raw.push_str(&rest);
-            }
+                },
+                timeouts.body_read.map($sleep),
+            )
+            .await;
+            let body = match body {
+                Some(Ok((body, trailers))) => {
+                    Request::append_trailers(&mut raw, &trailers);
+                    body
+                }
+                Some(Err(status)) => return Request::reject(status),
+                None => return Request::reject(StatusCode::RequestTimeout),
+            };
 
-            crate::core::request::Request::parse_raw_async(raw, routes, file_bases).await
+            Request::parse_raw(raw, body)
         }
     };
 }
@@ -78,14 +151,70 @@ macro_rules! create_async_parse_stream {
   feature = "async_std",
   feature = "async_smol"
 ))]
-use crate::core::request_handler::Rh;
+use crate::core::extract::States;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use crate::core::route_tree::{RouteMatch, RouteTree};
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use crate::core::middleware::{Endpoint, Middleware, Next};
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use async_trait::async_trait;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use std::sync::Arc;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use crate::core::request_type::{is_token, RequestType, Rt};
 #[cfg(any(
   feature = "sync",
   feature = "async_tokio",
   feature = "async_std",
   feature = "async_smol"
 ))]
-use crate::core::request_type::{RequestType, Rt};
+use crate::core::headers::Headers;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use crate::core::limits::Limits;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use crate::core::body::Body;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use crate::core::param::ParamError;
 #[cfg(any(
   feature = "sync",
   feature = "async_tokio",
@@ -106,7 +235,21 @@ use crate::core::status_code::StatusCode;
   feature = "async_std",
   feature = "async_smol"
 ))]
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use std::collections::HashMap;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use crate::core::utils::{parse_urlencoded, percent_decode};
 #[cfg(any(
   feature = "sync",
   feature = "async_tokio",
@@ -114,8 +257,19 @@ use std::collections::{BTreeMap, HashMap};
   feature = "async_smol"
 ))]
 use std::path::Path;
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+use std::str::FromStr;
 #[cfg(feature = "sync")]
-use std::net::TcpStream;
+use crate::core::timeouts::TimedStream;
+#[cfg(feature = "sync")]
+use crate::core::timeouts::Timeouts;
+#[cfg(feature = "sync")]
+use std::io::BufReader;
 
 #[cfg(feature = "async_tokio")]
 use tokio;
@@ -127,45 +281,189 @@ use futures_lite;
 use smol;
 
 create_async_parse_stream!(
+    /// Reads and parses one request from a Tokio connection.
     #[cfg(feature = "async_tokio")]
     parse_stream_tokio,
-    tokio::net::TcpStream,
-    tokio::io::BufReader<_>,
+    tokio::io::BufReader<tokio::net::TcpStream>,
     tokio::io::AsyncReadExt,
-    tokio::io::AsyncBufReadExt
+    tokio::io::AsyncBufReadExt,
+    tokio::time::sleep
 );
 
 create_async_parse_stream!(
+    /// Reads and parses one request from an async-std connection.
     #[cfg(feature = "async_std")]
     parse_stream_async_std,
-    async_std::net::TcpStream,
-    async_std::io::BufReader<_>,
+    async_std::io::BufReader<async_std::net::TcpStream>,
     async_std::io::ReadExt,
-    async_std::io::BufReadExt
+    async_std::io::BufReadExt,
+    async_std::task::sleep
 );
 
 create_async_parse_stream!(
+    /// Reads and parses one request from a smol connection.
     #[cfg(feature = "async_smol")]
     parse_stream_smol,
-    smol::net::TcpStream,
-    futures_lite::io::BufReader<_>,
+    futures_lite::io::BufReader<smol::net::TcpStream>,
     futures_lite::io::AsyncReadExt,
-    futures_lite::io::AsyncBufReadExt
+    futures_lite::io::AsyncBufReadExt,
+    smol::Timer::after
 );
 
+/// How the body of a request is delimited, as announced by its head.
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+enum Framing {
+  Empty,
+  Length(usize),
+  Chunked,
+  Reject(StatusCode),
+}
+
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+impl Framing {
+  /// Reads the framing off the raw request head.
+  ///
+  /// Only `chunked` is supported as a `Transfer-Encoding`. A request that
+  /// also sends `Content-Length` is rejected, since a proxy in front may
+  /// have framed it by the length (RFC 9112 §6.1). A request announcing
+  /// neither has no body (RFC 9112 §6.3); for the methods that normally
+  /// carry one, 411 is answered instead so a body sent without framing is
+  /// not taken for the next request.
+  fn of(raw: &str) -> Self {
+    let mut lines = raw.split("\r\n");
+    let method = lines
+      .next()
+      .and_then(|l| l.split_whitespace().next())
+      .unwrap_or("");
+    let Some(headers) = Headers::parse(lines.take_while(|l| !l.is_empty())) else {
+      // The malformed head is rejected once it gets parsed.
+      return Framing::Empty;
+    };
+
+    let codings: Vec<String> = headers
+      .get_all("Transfer-Encoding")
+      .iter()
+      .flat_map(|v| v.split(','))
+      .map(|c| c.trim().to_ascii_lowercase())
+      .filter(|c| !c.is_empty())
+      .collect();
+    if let Some(last) = codings.last() {
+      return if headers.contains("Content-Length") || last != "chunked" {
+        Framing::Reject(StatusCode::BadRequest)
+      } else if codings.len() > 1 {
+        Framing::Reject(StatusCode::NotImplemented)
+      } else {
+        Framing::Chunked
+      };
+    }
+
+    let lengths = headers.get_all("Content-Length");
+    if !lengths.is_empty() {
+      let mut values = lengths.iter().flat_map(|v| v.split(',')).map(|v| v.trim());
+      let first = values.next().unwrap_or("");
+      if first.is_empty() || !first.bytes().all(|b| b.is_ascii_digit()) || values.any(|v| v != first) {
+        return Framing::Reject(StatusCode::BadRequest);
+      }
+      return match first.parse() {
+        Ok(0) => Framing::Empty,
+        Ok(length) => Framing::Length(length),
+        Err(_) => Framing::Reject(StatusCode::BadRequest),
+      };
+    }
+
+    match method {
+      "POST" | "PUT" | "PATCH" => Framing::Reject(StatusCode::LengthRequired),
+      _ => Framing::Empty,
+    }
+  }
+}
+
+/// Longest chunk-size line accepted, extensions included.
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+const MAX_CHUNK_LINE: u64 = 4096;
+
+/// Whether a read failed because its deadline passed.
+#[cfg(feature = "sync")]
+fn is_timeout(error: &std::io::Error) -> bool {
+  matches!(error.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock)
+}
+
+/// The status answering a read of the request that failed with `error`.
+#[cfg(feature = "sync")]
+fn read_failure(error: std::io::Error) -> StatusCode {
+  if is_timeout(&error) {
+    StatusCode::RequestTimeout
+  } else {
+    StatusCode::BadRequest
+  }
+}
+
+/// A line of the request head as text. Bytes that aren't UTF-8 are obs-text
+/// (RFC 9110 §5.5) and are read as ISO-8859-1, so they never cut the head
+/// short.
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+fn head_line(bytes: Vec<u8>) -> String {
+  String::from_utf8(bytes).unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect())
+}
+
+/// Parses a chunk-size line, ignoring chunk extensions.
 #[cfg(any(
   feature = "sync",
   feature = "async_tokio",
   feature = "async_std",
   feature = "async_smol"
 ))]
+fn chunk_size(line: &str) -> Option<usize> {
+  let line = line.strip_suffix("\r\n")?;
+  let size = line.split(';').next()?.trim_end_matches([' ', '\t']);
+  if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return None;
+  }
+  usize::from_str_radix(size, 16).ok()
+}
+
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+#[derive(Clone)]
 pub struct Request {
   pub method: RequestType,
   pub path: String,
   pub version: String,
-  pub headers: Vec<(String, String)>,
-  pub body: String,
+  pub headers: Headers,
+  pub body: Vec<u8>,
+  /// Path captures and query pairs merged, path captures taking precedence
+  /// and repeated query keys keeping their last value. Kept for backwards
+  /// compatibility; prefer [`Request::path_params`] and [`Request::query`].
   pub params: HashMap<String, String>,
+  /// The request-target as sent on the request line, before any decoding.
+  pub raw_target: String,
+  path_params: HashMap<String, String>,
+  query: Vec<(String, String)>,
+  states: Arc<States>,
 }
 
 #[cfg(any(
@@ -175,336 +473,444 @@ pub struct Request {
   feature = "async_smol"
 ))]
 impl Request {
-  fn extract_params(route: &str, path: &str) -> HashMap<String, String> {
-    let mut sorted: BTreeMap<String, String> = BTreeMap::new();
-    let route_parts = route.split('/').collect::<Vec<_>>();
-    let path_parts = path.split('/').collect::<Vec<_>>();
-    if route_parts.len() != path_parts.len() {
-      return HashMap::new();
-    }
-    for (i, part) in route_parts.iter().enumerate() {
-      if part.starts_with('{') && part.ends_with('}') {
-        let key = part.trim_matches(&['{', '}'][..]).to_string();
-        sorted.insert(key, path_parts[i].to_string());
-      } else if *part != path_parts[i] {
-        return HashMap::new();
-      }
-    }
-    sorted.into_iter().collect()
-  }
-
+  /// Reads and parses one request from a connection, without routing it.
+  ///
+  /// The reader is owned by the connection so that bytes buffered past the
+  /// end of this request (a pipelined follow-up) are not lost. Nothing
+  /// beyond `limits` is read, and reading the head and the body is bounded
+  /// by `timeouts`.
   #[cfg(feature = "sync")]
   pub fn parse_stream_sync(
-    stream: &TcpStream,
-    routes: &HashMap<(Rt, String), Rh>,
-    file_bases: &[String],
+    reader: &mut BufReader<TimedStream<'_>>,
+    routes: &RouteTree,
+    limits: &Limits,
+    timeouts: &Timeouts,
   ) -> (Self, Option<Response>) {
-    use std::io::{BufRead, BufReader, Read};
+    use std::io::{BufRead, Read};
 
-    let mut reader = BufReader::new(stream);
     let mut raw = String::new();
+    let mut lines = 0;
 
-    // Read only headers
+    // Read only headers, within the limits
+    reader.get_mut().set_timeout(timeouts.header_read);
     loop {
-      let mut line = String::new();
-      if reader.read_line(&mut line).ok().filter(|&n| n > 0).is_none() {
-        break;
+      let budget = limits.max_header_bytes.saturating_sub(raw.len()) as u64;
+      let mut line = Vec::new();
+      match reader.by_ref().take(budget).read_until(b'\n', &mut line) {
+        Ok(0) => break,
+        Ok(_) => {}
+        Err(e) => return Self::reject(read_failure(e)),
       }
-      raw.push_str(&line);
+      raw.push_str(&head_line(line));
+      lines += 1;
       if raw.contains("\r\n\r\n") {
         break;
       }
+      if lines > limits.max_headers + 1 {
+        return Self::reject(StatusCode::RequestHeaderFieldsTooLarge);
+      }
+    }
+    if raw.len() >= limits.max_header_bytes && !raw.contains("\r\n\r\n") {
+      return Self::reject(StatusCode::RequestHeaderFieldsTooLarge);
     }
 
-    // Extract method from the first line
-    let method = raw
-      .lines()
-      .next()
-      .and_then(|l| l.split_whitespace().next())
-      .unwrap_or("");
+    reader.get_mut().set_timeout(timeouts.body_read);
+    let max_body = Self::body_limit(&raw, routes, limits);
+    let body = match Framing::of(&raw) {
+      Framing::Empty => Vec::new(),
+      Framing::Length(length) if length > max_body => return Self::reject(StatusCode::PayloadTooLarge),
+      Framing::Length(length) => {
+        let mut body = vec![0; length];
+        if let Err(e) = reader.read_exact(&mut body) {
+          return Self::reject(read_failure(e));
+        }
+        body
+      }
+      Framing::Chunked => match Self::read_chunked_sync(reader, raw.len(), max_body, limits) {
+        Ok((body, trailers)) => {
+          Self::append_trailers(&mut raw, &trailers);
+          body
+        }
+        Err(status) => return Self::reject(status),
+      },
+      Framing::Reject(status) => return Self::reject(status),
+    };
+
+    Self::parse_raw(raw, body)
+  }
 
-    // Determine length
-    let content_length = raw
-      .lines()
-      .find_map(|l| {
-        if l.to_ascii_lowercase().starts_with("content-length:") {
-          l.split(':').nth(1)?.trim().parse::<usize>().ok()
+  /// Decodes a `Transfer-Encoding: chunked` body, returning it along with
+  /// the raw trailer lines, or the status to answer when the framing is
+  /// broken, a limit is exceeded or time runs out. `head_len` is the size of
+  /// the header block already read, which the trailers count against.
+  #[cfg(feature = "sync")]
+  fn read_chunked_sync(
+    reader: &mut BufReader<TimedStream<'_>>,
+    head_len: usize,
+    max_body: usize,
+    limits: &Limits,
+  ) -> Result<(Vec<u8>, String), StatusCode> {
+    use std::io::{BufRead, Read};
+
+    let mut body = Vec::new();
+    loop {
+      let mut line = String::new();
+      reader.by_ref().take(MAX_CHUNK_LINE).read_line(&mut line).map_err(read_failure)?;
+      let size = chunk_size(&line).ok_or(StatusCode::BadRequest)?;
+      if size == 0 {
+        break;
+      }
+      if size > max_body - body.len() {
+        return Err(StatusCode::PayloadTooLarge);
+      }
+      let read = reader.by_ref().take(size as u64).read_to_end(&mut body).map_err(read_failure)?;
+      let mut crlf = [0; 2];
+      reader.read_exact(&mut crlf).map_err(read_failure)?;
+      if read != size || &crlf != b"\r\n" {
+        return Err(StatusCode::BadRequest);
+      }
+    }
+
+    // Trailer fields up to the closing empty line
+    let mut trailers = String::new();
+    let mut count = 0;
+    loop {
+      let budget = limits.max_header_bytes.saturating_sub(head_len + trailers.len()) as u64;
+      let mut line = String::new();
+      if reader.by_ref().take(budget).read_line(&mut line).map_err(read_failure)? == 0 {
+        return Err(if budget == 0 {
+          StatusCode::RequestHeaderFieldsTooLarge
         } else {
-          None
-        }
-      })
-      .unwrap_or(0);
-
-    if content_length > 0 {
-      // Read exactly content_length
-      let mut buf = vec![0; content_length];
-      let _ = reader.read_exact(&mut buf);
-      raw.push_str(&String::from_utf8_lossy(&buf));
-    } else if method != "GET" {
-      // Read all until EOF for POST/PUT/DELETE without Content-Length
-      let mut rest = String::new();
-      let _ = reader.read_to_string(&mut rest);
-      raw.push_str(&rest);
+          StatusCode::BadRequest
+        });
+      }
+      if line == "\r\n" {
+        return Ok((body, trailers));
+      }
+      trailers.push_str(&line);
+      count += 1;
+      if count > limits.max_headers {
+        return Err(StatusCode::RequestHeaderFieldsTooLarge);
+      }
     }
+  }
+
+  /// The body limit for the request whose head is `raw`: the one set on its
+  /// route, if any, or else the server-wide one.
+  fn body_limit(raw: &str, routes: &RouteTree, limits: &Limits) -> usize {
+    let mut parts = raw.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+      return limits.max_body_bytes;
+    };
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    routes
+      .find(&RequestType::from_str(method), path)
+      .and_then(|found| found.max_body)
+      .unwrap_or(limits.max_body_bytes)
+  }
 
-    Self::parse_raw_sync(raw, routes, file_bases)
+  /// Adds the trailer lines of a chunked body to the header block, so they
+  /// are parsed, and validated, like the other fields.
+  fn append_trailers(raw: &mut String, trailers: &str) {
+    if let Some(head) = raw.strip_suffix("\r\n") {
+      let head_len = head.len();
+      raw.truncate(head_len);
+      raw.push_str(trailers);
+      raw.push_str("\r\n");
+    }
   }
 
-  #[cfg(feature = "sync")]
-  pub fn parse_raw_sync(
-    raw: String,
-    routes: &HashMap<(Rt, String), Rh>,
-    file_bases: &[String],
-  ) -> (Self, Option<Response>) {
+  /// Answers a request that could not be read or parsed. The request is
+  /// left empty, so the connection is closed afterwards.
+  fn reject(status: StatusCode) -> (Self, Option<Response>) {
+    (
+      Self::default(),
+      Some(Response {
+        status: status.to_string(),
+        content_type: String::new(),
+        content: Body::default(),
+        headers: Headers::new(),
+      }),
+    )
+  }
+
+  /// Parses and validates a request without routing it. The response is
+  /// set when the request has to be refused (400, 414 or 505).
+  pub fn parse_raw(raw: String, body: Vec<u8>) -> (Self, Option<Response>) {
     if raw.trim().is_empty() {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::BadRequest.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
+      return Self::reject(StatusCode::BadRequest);
     }
     let parts: Vec<&str> = raw.split_whitespace().collect();
     if parts.len() < 3 {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::BadRequest.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
+      return Self::reject(StatusCode::BadRequest);
     }
     let method_str = parts[0];
     let path_str = parts[1];
     let version = parts[2];
-    let allowed = ["GET", "POST", "PUT", "DELETE"];
-    if !allowed.contains(&method_str) {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::MethodNotAllowed.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
+    if !is_token(method_str) {
+      return Self::reject(StatusCode::BadRequest);
     }
     if version != "HTTP/1.1" {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::HttpVersionNotSupported.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
+      return Self::reject(StatusCode::HttpVersionNotSupported);
     }
     const MAX_URI: usize = 2000;
     if path_str.len() > MAX_URI {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::UriTooLong.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
+      return Self::reject(StatusCode::UriTooLong);
+    }
+    match Self::parse_raw_only(raw, body) {
+      Some(req) => (req, None),
+      None => Self::reject(StatusCode::BadRequest),
+    }
+  }
+
+  #[cfg(feature = "sync")]
+  pub fn parse_raw_sync(
+    raw: String,
+    body: Vec<u8>,
+    routes: &RouteTree,
+    file_bases: &[String],
+  ) -> (Self, Option<Response>) {
+    let (mut req, early) = Self::parse_raw(raw, body);
+    if early.is_some() {
+      return (req, early);
     }
-    let mut req = Self::parse_raw_only(raw, routes);
     let early = req.route_sync(routes, file_bases);
     (req, early)
   }
 
-#[cfg(any(feature = "async_tokio", feature = "async_std", feature = "async_smol"))]
+  #[cfg(any(feature = "async_tokio", feature = "async_std", feature = "async_smol"))]
   pub async fn parse_raw_async(
     raw: String,
-    routes: &HashMap<(Rt, String), Rh>,
+    body: Vec<u8>,
+    routes: &RouteTree,
     file_bases: &[String],
   ) -> (Self, Option<Response>) {
-    if raw.trim().is_empty() {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::BadRequest.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
-    }
-    let parts: Vec<&str> = raw.split_whitespace().collect();
-    if parts.len() < 3 {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::BadRequest.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
-    }
-    let method_str = parts[0];
-    let path_str = parts[1];
-    let version = parts[2];
-    let allowed = ["GET", "POST", "PUT", "DELETE"];
-    if !allowed.contains(&method_str) {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::MethodNotAllowed.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
-    }
-    if version != "HTTP/1.1" {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::HttpVersionNotSupported.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
-    }
-    const MAX_URI: usize = 2000;
-    if path_str.len() > MAX_URI {
-      return (
-        Self::default(),
-        Some(Response {
-          status: StatusCode::UriTooLong.to_string(),
-          content_type: String::new(),
-          content: Vec::new(),
-        }),
-      );
+    let (mut req, early) = Self::parse_raw(raw, body);
+    if early.is_some() {
+      return (req, early);
     }
-    let mut req = Self::parse_raw_only(raw, routes);
     // route is async under these features, await it here
     let early = req.route_async(routes, file_bases).await;
     (req, early)
   }
 
-  fn parse_raw_only(raw: String, routes: &HashMap<(Rt, String), Rh>) -> Self {
-    let lines: Vec<&str> = raw.split("\r\n").collect();
-    let mut cut = 0;
-    for (i, &l) in lines.iter().enumerate() {
-      if l.trim().is_empty() {
-        cut = i;
-        break;
-      }
-    }
-    let headers = lines[..cut]
-      .iter()
-      .filter_map(|&h| {
-        let p: Vec<&str> = h.split(": ").collect();
-        (p.len() == 2).then(|| (p[0].to_string(), p[1].to_string()))
-      })
-      .collect();
-    let body = lines[cut + 1..].join("\r\n");
+  /// Builds the request from its head; `None` when a header line is malformed.
+  fn parse_raw_only(raw: String, body: Vec<u8>) -> Option<Self> {
+    let headers = Headers::parse(raw.split("\r\n").skip(1).take_while(|l| !l.is_empty()))?;
     let parts: Vec<&str> = raw.split_whitespace().collect();
-    let mut path = parts[1].to_string();
-    let mut params = HashMap::new();
-    let query_opt = if let Some(qpos) = path.find('?') {
-      let qs = path[qpos + 1..].to_string();
-      path.truncate(qpos);
-      Some(qs)
-    } else {
-      None
+    let raw_target = parts[1].to_string();
+    let (raw_path, query) = match raw_target.split_once('?') {
+      Some((path, query)) => (path, Some(query)),
+      None => (raw_target.as_str(), None),
     };
-    for (m, rp) in routes.keys() {
-      if *m == RequestType::from_str(parts[0]) {
-        for (k, v) in Self::extract_params(rp, &path) {
-          params.insert(k, v);
-        }
-        break;
-      }
-    }
-    if let Some(qs) = query_opt {
-      for p in qs.split('&') {
-        if let Some(eq) = p.find('=') {
-          params.insert(p[..eq].to_string(), p[eq + 1..].to_string());
-        }
-      }
-    }
-    Request {
+    let query = parse_urlencoded(query.unwrap_or(""));
+    let params = query.iter().cloned().collect();
+    Some(Request {
       method: RequestType::from_str(parts[0]),
-      path,
+      path: percent_decode(raw_path, false),
       version: parts[2].to_string(),
       headers,
       body,
       params,
-    }
+      raw_target,
+      path_params: HashMap::new(),
+      query,
+      states: Arc::default(),
+    })
+  }
+
+  /// The path exactly as received, still percent-encoded and without the query.
+  pub fn raw_path(&self) -> &str {
+    self.raw_target.split('?').next().unwrap_or("")
   }
 
   #[cfg(feature = "sync")]
-  pub fn route_sync(&mut self, routes: &HashMap<(Rt, String), Rh>, file_bases: &[String]) -> Option<Response> {
-    if let Some(rh) = routes.get(&(self.method.clone(), self.path.clone())) {
-      return Some(futures::executor::block_on(rh.handler.handle(self)));
-    }
-    for ((m, rp), rh) in routes {
-      if *m == self.method {
-        let path_p = Self::extract_params(rp, &self.path);
-        if !path_p.is_empty() {
-          let mut merged = HashMap::new();
-          for (k, v) in path_p {
-            merged.insert(k, v);
-          }
-          for (k, v) in self.params.drain() {
-            merged.insert(k, v);
-          }
-          self.params = merged;
-          return Some(futures::executor::block_on(rh.handler.handle(self)));
-        }
+  pub fn route_sync(&mut self, routes: &RouteTree, file_bases: &[String]) -> Option<Response> {
+    Some(futures::executor::block_on(self.route(routes, file_bases)))
+  }
+
+  #[cfg(any(feature = "async_tokio", feature = "async_std", feature = "async_smol"))]
+  pub async fn route_async(&mut self, routes: &RouteTree, file_bases: &[String]) -> Option<Response> {
+    Some(self.route(routes, file_bases).await)
+  }
+
+  /// Answers the request through the server-wide middlewares, which see
+  /// unrouted requests too.
+  async fn route(&mut self, routes: &RouteTree, file_bases: &[String]) -> Response {
+    self.states = Arc::clone(routes.states());
+    let dispatch = Dispatch { routes, file_bases };
+    Next::new(routes.middlewares(), &dispatch).run(self).await
+  }
+
+  /// Hands the request to its route's handler, through the route's group
+  /// and own middlewares, or answers it as unrouted: with the fallback
+  /// handler when nothing at all matches, and otherwise with the error
+  /// pages for the statuses the server sets.
+  async fn dispatch(&mut self, routes: &RouteTree, file_bases: &[String]) -> Response {
+    let Some(found) = self.find_route(routes) else {
+      return match (self.route_miss(routes, file_bases), routes.fallback()) {
+        (Some(response), _) => routes.error_pages().apply(self, response),
+        (None, Some(fallback)) => fallback.handle(self).await,
+        (None, None) => routes.error_pages().apply(self, Response::new()),
+      };
+    };
+    self.merge_path_params(found.params);
+    let chain: Vec<Arc<dyn Middleware>> = routes
+      .group_middlewares(found.pattern)
+      .chain(found.middlewares)
+      .cloned()
+      .collect();
+    Next::new(&chain, &found.handler.handler).run(self).await
+  }
+
+  /// Looks up the route for this request. HEAD falls back to the GET route
+  /// when it has none of its own; the writer then drops the body.
+  fn find_route<'r>(&self, routes: &'r RouteTree) -> Option<RouteMatch<'r>> {
+    routes.find(&self.method, self.raw_path()).or_else(|| {
+      if self.method == Rt::HEAD {
+        routes.find(&Rt::GET, self.raw_path())
+      } else {
+        None
       }
+    })
+  }
+
+  /// Methods the path answers to, including the HEAD derived from GET and the
+  /// automatic OPTIONS. Empty when the path is not routed.
+  fn allowed_methods(&self, routes: &RouteTree) -> Vec<Rt> {
+    let mut allowed = routes.allowed_methods(self.raw_path());
+    if allowed.is_empty() {
+      return allowed;
     }
-    if self.method == Rt::GET {
-      return Some(self.serve_file(file_bases));
+    if allowed.contains(&Rt::GET) {
+      allowed.push(Rt::HEAD);
     }
-    None
+    allowed.push(Rt::OPTIONS);
+    allowed.sort();
+    allowed.dedup();
+    allowed
   }
 
-#[cfg(any(feature = "async_tokio", feature = "async_std", feature = "async_smol"))]
-  pub async fn route_async(&mut self, routes: &HashMap<(Rt, String), Rh>, file_bases: &[String]) -> Option<Response> {
-    if let Some(rh) = routes.get(&(self.method.clone(), self.path.clone())) {
-      return Some(rh.handler.handle(self).await);
+  /// Answer for a request no route handles: 501 for an extension method no
+  /// route uses; the automatic OPTIONS reply or a 405, both with an `Allow`
+  /// header, when the path is routed under other methods; a static file for
+  /// GET and HEAD, when there is one; and `None` (404) otherwise.
+  fn route_miss(&self, routes: &RouteTree, file_bases: &[String]) -> Option<Response> {
+    if matches!(self.method, Rt::Extension(_)) && !routes.has_method(&self.method) {
+      return Some(
+        Response::builder()
+          .status(StatusCode::NotImplemented)
+          .content_type("text/plain")
+          .body(StatusCode::NotImplemented.to_string()),
+      );
     }
-    for ((m, rp), rh) in routes {
-      if *m == self.method {
-        let path_p = Self::extract_params(rp, &self.path);
-        if !path_p.is_empty() {
-          let mut merged = HashMap::new();
-          for (k, v) in path_p {
-            merged.insert(k, v);
-          }
-          for (k, v) in self.params.drain() {
-            merged.insert(k, v);
-          }
-          self.params = merged;
-          return Some(rh.handler.handle(self).await);
-        }
+    let allowed = self.allowed_methods(routes);
+    if !allowed.is_empty() {
+      let allow = allowed.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ");
+      if self.method == Rt::OPTIONS {
+        return Some(
+          Response::builder()
+            .status(StatusCode::NoContent)
+            .header("Allow", allow)
+            .body(Vec::new()),
+        );
       }
+      return Some(
+        Response::builder()
+          .status(StatusCode::MethodNotAllowed)
+          .content_type("text/plain")
+          .header("Allow", allow)
+          .body(StatusCode::MethodNotAllowed.to_string()),
+      );
     }
-    if self.method == Rt::GET {
-      return Some(self.serve_file(file_bases));
+    if self.method == Rt::GET || self.method == Rt::HEAD {
+      return self.serve_file(file_bases);
     }
     None
   }
 
-  fn serve_file(&self, bases: &[String]) -> Response {
+  /// Stores the captured path parameters, also layering them over the query
+  /// pairs in `params`.
+  fn merge_path_params(&mut self, path_params: HashMap<String, String>) {
+    self.params.extend(path_params.clone());
+    self.path_params = path_params;
+  }
+
+  /// Parameters captured from the route pattern, e.g. `id` in `/users/{id}`.
+  pub fn path_params(&self) -> &HashMap<String, String> {
+    &self.path_params
+  }
+
+  /// The server's shared state of type `T`, added with `Server::add_state`.
+  pub fn state<T: 'static>(&self) -> Option<&T> {
+    self.states.get()
+  }
+
+  /// Decoded query pairs in the order they were sent, repeated keys included.
+  pub fn query(&self) -> &[(String, String)] {
+    &self.query
+  }
+
+  /// The first value of a query key.
+  pub fn query_param(&self, key: &str) -> Option<&str> {
+    self.query.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+  }
+
+  /// Every value of a repeated query key, e.g. `["a", "b"]` for `?tag=a&tag=b`.
+  pub fn query_all(&self, key: &str) -> Vec<&str> {
+    self
+      .query
+      .iter()
+      .filter(|(k, _)| k == key)
+      .map(|(_, v)| v.as_str())
+      .collect()
+  }
+
+  /// Reads a path parameter as `T`, e.g. `request.param::<u64>("id")`.
+  pub fn param<T: FromStr>(&self, name: &str) -> Result<T, ParamError> {
+    let value = self
+      .path_params
+      .get(name)
+      .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+    value.parse().map_err(|_| ParamError::Invalid {
+      name: name.to_string(),
+      value: value.clone(),
+    })
+  }
+
+  /// The body decoded as UTF-8, with invalid sequences replaced by `U+FFFD`.
+  pub fn body_str(&self) -> Cow<'_, str> {
+    String::from_utf8_lossy(&self.body)
+  }
+
+  /// Whether the connection has to end after this request: the client sent
+  /// `Connection: close`, or the request could not be parsed and the rest of
+  /// the stream can't be trusted to start at a request boundary.
+  pub fn closes_connection(&self) -> bool {
+    self.version.is_empty()
+      || self
+        .headers
+        .get_all("Connection")
+        .iter()
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("close"))
+  }
+
+  fn serve_file(&self, bases: &[String]) -> Option<Response> {
     for base in bases {
       let base_path = Path::new(base);
       if let Some(real_path) = crate::core::utils::secure_path(base_path, &self.path) {
-        if let Ok(data) = std::fs::read(&real_path) {
-          return Response {
+        if let Ok(file) = std::fs::File::open(&real_path) {
+          return Some(Response {
             status: StatusCode::Ok.to_string(),
             content_type: crate::core::utils::get_content_type_quick(&real_path),
-            content: data,
-          };
+            content: Body::File(file),
+            headers: Headers::new(),
+          });
         }
       }
     }
-    Response::new()
+    None
   }
 }
 
@@ -520,9 +926,13 @@ impl Default for Request {
       method: RequestType::GET,
       path: String::new(),
       version: String::new(),
-      headers: vec![],
-      body: String::new(),
+      headers: Headers::new(),
+      body: Vec::new(),
       params: HashMap::new(),
+      raw_target: String::new(),
+      path_params: HashMap::new(),
+      query: Vec::new(),
+      states: Arc::default(),
     }
   }
 }
@@ -560,15 +970,45 @@ impl Display for Request {
        Headers: {:#?},\n\
        Body: {}\n\
        Params: {}",
-      self.method, self.path, self.version, self.headers, self.body, params_str
+      self.method,
+      self.path,
+      self.version,
+      self.headers,
+      self.body_str(),
+      params_str
     )
   }
 }
 
+/// The end of the server-wide middleware chain.
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+struct Dispatch<'a> {
+  routes: &'a RouteTree,
+  file_bases: &'a [String],
+}
+
+#[cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+#[async_trait]
+impl Endpoint for Dispatch<'_> {
+  async fn call(&self, request: &mut Request) -> Response {
+    request.dispatch(self.routes, self.file_bases).await
+  }
+}
+
 #[cfg(feature = "sync")]
 pub fn handle_request_sync(
   req: &mut Request,
-  routes: &HashMap<(Rt, String), Rh>,
+  routes: &RouteTree,
   file_bases: &[String],
 ) -> Option<Response> {
   req.route_sync(routes, file_bases)
@@ -577,7 +1017,7 @@ pub fn handle_request_sync(
 #[cfg(any(feature = "async_tokio", feature = "async_std", feature = "async_smol"))]
 pub async fn handle_request_async(
   req: &mut Request,
-  routes: &HashMap<(Rt, String), Rh>,
+  routes: &RouteTree,
   file_bases: &[String],
 ) -> Option<Response> {
   req.route_async(routes, file_bases).await
@@ -2,7 +2,7 @@ use std::fmt::{self, Display, Formatter};
 
 pub type Rt = RequestType;
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum RequestType {
   GET,
   POST,
@@ -12,15 +12,22 @@ pub enum RequestType {
   OPTIONS,
   CONNECT,
   PATCH,
+  /// Any other method token, e.g. `PROPFIND`, `MKCOL` or `PURGE`, kept verbatim.
+  Extension(String),
 }
 
 impl Display for RequestType {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-    write!(f, "{:?}", self)
+    match self {
+      RequestType::Extension(method) => write!(f, "{}", method),
+      known => write!(f, "{:?}", known),
+    }
   }
 }
 
 impl RequestType {
+  /// Parses a method name. The standard methods are matched case-insensitively;
+  /// anything else becomes [`RequestType::Extension`].
   pub fn from_str(s: &str) -> Self {
     match s.to_uppercase().as_str() {
       "GET" => RequestType::GET,
@@ -31,7 +38,15 @@ impl RequestType {
       "OPTIONS" => RequestType::OPTIONS,
       "CONNECT" => RequestType::CONNECT,
       "PATCH" => RequestType::PATCH,
-      _ => RequestType::GET,
+      _ => RequestType::Extension(s.to_string()),
     }
   }
 }
+
+/// Whether `s` is a valid method token (RFC 9110, section 5.6.2).
+pub fn is_token(s: &str) -> bool {
+  !s.is_empty()
+    && s
+      .bytes()
+      .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
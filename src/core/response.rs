@@ -1,12 +1,15 @@
 use std::fmt::{Display, Formatter, Result};
 
+use crate::core::body::Body;
+use crate::core::headers::Headers;
 use crate::core::status_code::StatusCode;
 
 #[derive(Debug)]
 pub struct Response {
   pub status: String,
   pub content_type: String,
-  pub content: Vec<u8>,
+  pub content: Body,
+  pub headers: Headers,
 }
 
 impl Default for Response {
@@ -14,7 +17,8 @@ impl Default for Response {
     Response {
       status: StatusCode::NotFound.to_string(),
       content_type: "text/plain".to_string(),
-      content: b"404 Not Found".to_vec(),
+      content: b"404 Not Found".into(),
+      headers: Headers::new(),
     }
   }
 }
@@ -29,4 +33,71 @@ impl Response {
   pub fn new() -> Self {
     Self::default()
   }
+
+  /// The status as a [`StatusCode`], when it is one of the listed ones.
+  pub fn status_code(&self) -> Option<StatusCode> {
+    let code = self.status.split(' ').next()?.parse::<u16>().ok()?;
+    StatusCode::try_from(code).ok()
+  }
+
+  /// Starts a fluent response, e.g.
+  /// `Response::builder().status(StatusCode::Found).header("Location", "/").body("")`.
+  pub fn builder() -> ResponseBuilder {
+    ResponseBuilder::new()
+  }
+}
+
+/// Fluent constructor for [`Response`], finished by [`ResponseBuilder::body`].
+#[derive(Debug)]
+pub struct ResponseBuilder {
+  status: String,
+  content_type: String,
+  headers: Headers,
+}
+
+impl Default for ResponseBuilder {
+  fn default() -> Self {
+    ResponseBuilder {
+      status: StatusCode::Ok.to_string(),
+      content_type: String::new(),
+      headers: Headers::new(),
+    }
+  }
+}
+
+impl ResponseBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn status(mut self, status: StatusCode) -> Self {
+    self.status = status.to_string();
+    self
+  }
+
+  pub fn content_type<S: Into<String>>(mut self, content_type: S) -> Self {
+    self.content_type = content_type.into();
+    self
+  }
+
+  /// Appends a header; calling it twice with the same name sends both values.
+  pub fn header<N, V>(mut self, name: N, value: V) -> Self
+  where
+    N: Into<String>,
+    V: Into<String>,
+  {
+    self.headers.append(name, value);
+    self
+  }
+
+  /// Finishes the response. Anything convertible to a [`Body`] works: bytes,
+  /// text, an open file or a [`Body::chunks`]/[`Body::stream`] producer.
+  pub fn body<B: Into<Body>>(self, body: B) -> Response {
+    Response {
+      status: self.status,
+      content_type: self.content_type,
+      content: body.into(),
+      headers: self.headers,
+    }
+  }
 }
@@ -0,0 +1,384 @@
+#![cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+
+use crate::core::error_pages::ErrorPages;
+use crate::core::extract::States;
+use crate::core::handler::Handler;
+use crate::core::middleware::Middleware;
+use crate::core::param::Constraint;
+use crate::core::request_handler::Rh;
+use crate::core::request_type::Rt;
+use crate::core::utils::percent_decode;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// A compiled routing trie keyed by path segment.
+///
+/// Lookups walk the request path once, trying static segments first, then
+/// constrained `{param:constraint}` segments, then plain `{param}` segments and
+/// finally a trailing catch-all (`{*rest}` or `*`), so overlapping routes such
+/// as `/test/static` and `/test/{a}` always resolve the same way regardless of
+/// registration order. Constraints can overlap in ways that can't be checked
+/// up front, so constrained siblings are the exception: they are tried in the
+/// order their first route was registered, and the first one matching wins.
+#[derive(Default)]
+pub struct RouteTree {
+  root: Node,
+  methods: HashSet<Rt>,
+  middlewares: Vec<Arc<dyn Middleware>>,
+  group_middlewares: Vec<(String, Arc<dyn Middleware>)>,
+  fallback: Option<Arc<dyn Handler>>,
+  error_pages: ErrorPages,
+  states: Arc<States>,
+}
+
+#[derive(Default)]
+struct Node {
+  statics: HashMap<String, Node>,
+  constrained: Vec<ConstrainedParam>,
+  param: Option<Box<Node>>,
+  catch_all: Option<Box<Node>>,
+  routes: HashMap<Rt, Route>,
+}
+
+struct ConstrainedParam {
+  source: String,
+  constraint: Constraint,
+  node: Node,
+}
+
+struct Route {
+  pattern: String,
+  names: Vec<String>,
+  handler: Rh,
+  max_body: Option<usize>,
+  middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+/// A route resolved for a request: its pattern and handler, the captured
+/// path parameters, the body limit set for it, if any, and the middlewares
+/// of its own.
+pub struct RouteMatch<'a> {
+  pub pattern: &'a str,
+  pub handler: &'a Rh,
+  pub params: HashMap<String, String>,
+  pub max_body: Option<usize>,
+  pub middlewares: &'a [Arc<dyn Middleware>],
+}
+
+enum Segment<'p> {
+  Static(&'p str),
+  Param(&'p str, Option<&'p str>),
+  CatchAll(&'p str),
+}
+
+fn parse_segment(segment: &str) -> Segment<'_> {
+  if segment == "*" {
+    Segment::CatchAll("*")
+  } else if segment.len() >= 3 && segment.starts_with("{*") && segment.ends_with('}') {
+    Segment::CatchAll(&segment[2..segment.len() - 1])
+  } else if segment.len() >= 2 && segment.starts_with('{') && segment.ends_with('}') {
+    let inner = &segment[1..segment.len() - 1];
+    match inner.split_once(':') {
+      Some((name, constraint)) => Segment::Param(name, Some(constraint)),
+      None => Segment::Param(inner, None),
+    }
+  } else {
+    Segment::Static(segment)
+  }
+}
+
+fn decode_segments(path: &str) -> Vec<String> {
+  path.split('/').map(|s| percent_decode(s, false)).collect()
+}
+
+impl RouteTree {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Registers `handler` for `method` on `pattern`.
+  ///
+  /// Registering the exact same pattern and method again replaces the
+  /// previous handler.
+  ///
+  /// # Panics
+  ///
+  /// Panics when the pattern can never be told apart from an already
+  /// registered one for the same method, e.g. `/users/{id}` and `/users/{name}`,
+  /// when a catch-all segment is not the last one, or when a constraint can't
+  /// be parsed.
+  pub fn insert(&mut self, pattern: &str, method: Rt, handler: Rh) {
+    let mut node = &mut self.root;
+    let mut names = Vec::new();
+    let mut segments = pattern.split('/').peekable();
+    while let Some(segment) = segments.next() {
+      node = match parse_segment(segment) {
+        Segment::Static(s) => node.statics.entry(s.to_string()).or_default(),
+        Segment::Param(name, None) => {
+          names.push(name.to_string());
+          node.param.get_or_insert_with(Default::default)
+        }
+        Segment::Param(name, Some(source)) => {
+          names.push(name.to_string());
+          let index = match node.constrained.iter().position(|c| c.source == source) {
+            Some(index) => index,
+            None => {
+              let constraint = Constraint::parse(source)
+                .unwrap_or_else(|e| panic!("invalid route: {} has a bad constraint `{}`: {}", pattern, source, e));
+              node.constrained.push(ConstrainedParam {
+                source: source.to_string(),
+                constraint,
+                node: Node::default(),
+              });
+              node.constrained.len() - 1
+            }
+          };
+          &mut node.constrained[index].node
+        }
+        Segment::CatchAll(name) => {
+          if segments.peek().is_some() {
+            panic!("invalid route: {} has a catch-all segment that is not the last one", pattern);
+          }
+          names.push(name.to_string());
+          node.catch_all.get_or_insert_with(Default::default)
+        }
+      };
+    }
+    if let Some(existing) = node.routes.get(&method)
+      && existing.pattern != pattern
+    {
+      panic!(
+        "ambiguous route: {} {} conflicts with already registered {} {}",
+        method, pattern, method, existing.pattern
+      );
+    }
+    self.methods.insert(method.clone());
+    node.routes.insert(
+      method,
+      Route {
+        pattern: pattern.to_string(),
+        names,
+        handler,
+        max_body: None,
+        middlewares: Vec::new(),
+      },
+    );
+  }
+
+  /// Sets the body limit of the route registered for `method` on exactly
+  /// `pattern`, overriding the server-wide one. Returns `false` when there is
+  /// no such route.
+  pub fn set_body_limit(&mut self, pattern: &str, method: &Rt, max_bytes: usize) -> bool {
+    match self.route_mut(pattern, method) {
+      Some(route) => {
+        route.max_body = Some(max_bytes);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Adds a middleware run on every request, routed or not.
+  pub fn add_middleware(&mut self, middleware: Arc<dyn Middleware>) {
+    self.middlewares.push(middleware);
+  }
+
+  /// Adds a middleware run on the routes whose pattern starts with the
+  /// segments of `prefix`, whenever they are registered.
+  pub fn add_group_middleware(&mut self, prefix: &str, middleware: Arc<dyn Middleware>) {
+    self.group_middlewares.push((prefix.trim_end_matches('/').to_string(), middleware));
+  }
+
+  /// Adds a middleware to the route registered for `method` on exactly
+  /// `pattern`. Returns `false` when there is no such route.
+  pub fn add_route_middleware(&mut self, pattern: &str, method: &Rt, middleware: Arc<dyn Middleware>) -> bool {
+    match self.route_mut(pattern, method) {
+      Some(route) => {
+        route.middlewares.push(middleware);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// The middlewares run on every request.
+  pub fn middlewares(&self) -> &[Arc<dyn Middleware>] {
+    &self.middlewares
+  }
+
+  /// The middlewares of every group `pattern` belongs to, in the order they
+  /// were added.
+  pub fn group_middlewares(&self, pattern: &str) -> impl Iterator<Item = &Arc<dyn Middleware>> {
+    self
+      .group_middlewares
+      .iter()
+      .filter(move |(prefix, _)| {
+        let mut segments = pattern.split('/');
+        prefix.split('/').all(|p| segments.next() == Some(p))
+      })
+      .map(|(_, middleware)| middleware)
+  }
+
+  /// Sets the handler answering requests that match no route and no static
+  /// file, in place of the 404.
+  pub fn set_fallback(&mut self, handler: Arc<dyn Handler>) {
+    self.fallback = Some(handler);
+  }
+
+  pub fn fallback(&self) -> Option<&Arc<dyn Handler>> {
+    self.fallback.as_ref()
+  }
+
+  /// Renderers for the error answers the server produces itself.
+  pub fn error_pages(&self) -> &ErrorPages {
+    &self.error_pages
+  }
+
+  pub fn error_pages_mut(&mut self) -> &mut ErrorPages {
+    &mut self.error_pages
+  }
+
+  /// The state shared by every handler, handed to each request.
+  pub fn states(&self) -> &Arc<States> {
+    &self.states
+  }
+
+  pub fn states_mut(&mut self) -> &mut States {
+    Arc::make_mut(&mut self.states)
+  }
+
+  /// The route registered for `method` on exactly `pattern`.
+  fn route_mut(&mut self, pattern: &str, method: &Rt) -> Option<&mut Route> {
+    let mut node = &mut self.root;
+    for segment in pattern.split('/') {
+      let next = match parse_segment(segment) {
+        Segment::Static(s) => node.statics.get_mut(s),
+        Segment::Param(_, None) => node.param.as_deref_mut(),
+        Segment::Param(_, Some(source)) => node
+          .constrained
+          .iter_mut()
+          .find(|c| c.source == source)
+          .map(|c| &mut c.node),
+        Segment::CatchAll(_) => node.catch_all.as_deref_mut(),
+      };
+      node = next?;
+    }
+    node.routes.get_mut(method).filter(|route| route.pattern == pattern)
+  }
+
+  /// Finds the handler registered for `method` that best matches `path`.
+  ///
+  /// `path` is the raw, still percent-encoded path: it is split on `/` first
+  /// and each segment decoded afterwards, so an encoded `%2F` stays inside
+  /// its segment.
+  pub fn find(&self, method: &Rt, path: &str) -> Option<RouteMatch<'_>> {
+    let decoded = decode_segments(path);
+    let segments: Vec<&str> = decoded.iter().map(String::as_str).collect();
+    let mut captures = Vec::new();
+    let mut found = None;
+    self.root.walk(&segments, &mut captures, &mut |node, values| {
+      match node.routes.get(method) {
+        Some(route) => {
+          let params = route
+            .names
+            .iter()
+            .cloned()
+            .zip(values.iter().cloned())
+            .collect();
+          found = Some(RouteMatch {
+            pattern: &route.pattern,
+            handler: &route.handler,
+            params,
+            max_body: route.max_body,
+            middlewares: &route.middlewares,
+          });
+          true
+        }
+        None => false,
+      }
+    });
+    found
+  }
+
+  /// Whether any route at all is registered for `method`.
+  pub fn has_method(&self, method: &Rt) -> bool {
+    self.methods.contains(method)
+  }
+
+  /// Lists every method with a route matching `path`, sorted and without
+  /// duplicates. Empty when the path is not routed at all.
+  pub fn allowed_methods(&self, path: &str) -> Vec<Rt> {
+    let decoded = decode_segments(path);
+    let segments: Vec<&str> = decoded.iter().map(String::as_str).collect();
+    let mut captures = Vec::new();
+    let mut methods = Vec::new();
+    self.root.walk(&segments, &mut captures, &mut |node, _| {
+      methods.extend(node.routes.keys().cloned());
+      false
+    });
+    methods.sort();
+    methods.dedup();
+    methods
+  }
+}
+
+impl Node {
+  /// Depth-first walk in precedence order (static, constrained parameter,
+  /// parameter, catch-all), calling `visit` on every node that consumes the
+  /// whole path. Stops as soon as `visit` returns `true`.
+  fn walk<'a>(
+    &'a self,
+    segments: &[&str],
+    captures: &mut Vec<String>,
+    visit: &mut dyn FnMut(&'a Node, &[String]) -> bool,
+  ) -> bool {
+    let Some((first, rest)) = segments.split_first() else {
+      return visit(self, captures);
+    };
+    if let Some(child) = self.statics.get(*first)
+      && child.walk(rest, captures, visit)
+    {
+      return true;
+    }
+    for param in &self.constrained {
+      if !param.constraint.matches(first) {
+        continue;
+      }
+      captures.push(first.to_string());
+      if param.node.walk(rest, captures, visit) {
+        return true;
+      }
+      captures.pop();
+    }
+    if let Some(child) = &self.param {
+      captures.push(first.to_string());
+      if child.walk(rest, captures, visit) {
+        return true;
+      }
+      captures.pop();
+    }
+    if let Some(child) = &self.catch_all {
+      captures.push(segments.join("/"));
+      if visit(child, captures) {
+        return true;
+      }
+      captures.pop();
+    }
+    false
+  }
+}
+
+impl From<HashMap<(Rt, String), Rh>> for RouteTree {
+  fn from(routes: HashMap<(Rt, String), Rh>) -> Self {
+    let mut tree = RouteTree::new();
+    for ((method, pattern), handler) in routes {
+      tree.insert(&pattern, method, handler);
+    }
+    tree
+  }
+}
@@ -0,0 +1,151 @@
+#![cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+
+use crate::core::handler::Handler;
+use crate::core::middleware::Middleware;
+use crate::core::request_handler::Rh;
+use crate::core::request_type::Rt;
+use crate::core::route_tree::RouteTree;
+use std::sync::Arc;
+
+/// A set of routes built apart from any server, e.g. in the module that
+/// owns them, then nested under a prefix or merged into a `Server`, a
+/// [`ServerBuilder`](crate::ServerBuilder) or another router.
+///
+/// ```ignore
+/// fn users_router() -> Router {
+///   Router::new()
+///     .route("/users", Rt::GET, handler!(list_users))
+///     .route("/users/{id}", Rt::GET, handler!(get_user))
+///     .middleware(require_token())
+/// }
+///
+/// server.nest("/api/v1", users_router());
+/// ```
+#[derive(Clone, Default)]
+pub struct Router {
+  routes: Vec<RouterRoute>,
+  middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+/// A route of a [`Router`], with its path relative to the router.
+#[derive(Clone)]
+pub(crate) struct RouterRoute {
+  pub path: String,
+  pub rt: Rt,
+  pub handler: Arc<dyn Handler>,
+  pub middlewares: Vec<Arc<dyn Middleware>>,
+  pub max_body: Option<usize>,
+}
+
+impl Router {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds a route; see `Server::add_route`.
+  pub fn route<S: Into<String>>(mut self, path: S, rt: Rt, handler: Arc<dyn Handler>) -> Self {
+    self.routes.push(RouterRoute {
+      path: path.into(),
+      rt,
+      handler,
+      middlewares: Vec::new(),
+      max_body: None,
+    });
+    self
+  }
+
+  /// Adds a middleware run on every route of this router, nested ones
+  /// included, before the routes' own middlewares.
+  pub fn middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+    self.middlewares.push(middleware);
+    self
+  }
+
+  /// Adds a middleware to the route added on `path` and method `rt`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if no such route has been added.
+  pub fn route_middleware(mut self, path: &str, rt: Rt, middleware: Arc<dyn Middleware>) -> Self {
+    self.route_mut(path, &rt).middlewares.push(middleware);
+    self
+  }
+
+  /// Overrides the body size limit of the route added on `path` and method
+  /// `rt`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if no such route has been added.
+  pub fn body_limit(mut self, path: &str, rt: Rt, max_bytes: usize) -> Self {
+    self.route_mut(path, &rt).max_body = Some(max_bytes);
+    self
+  }
+
+  /// Adds the routes of `router` with `prefix` in front of their paths.
+  pub fn nest(mut self, prefix: &str, router: Router) -> Self {
+    self.routes.extend(router.into_routes(prefix));
+    self
+  }
+
+  /// Adds the routes of `router` as they are.
+  pub fn merge(self, router: Router) -> Self {
+    self.nest("", router)
+  }
+
+  /// The routes with `prefix` in front of their paths and the router's
+  /// middlewares in front of their own.
+  pub(crate) fn into_routes(self, prefix: &str) -> Vec<RouterRoute> {
+    let middlewares = self.middlewares;
+    self
+      .routes
+      .into_iter()
+      .map(|route| RouterRoute {
+        path: join_path(prefix, &route.path),
+        middlewares: middlewares.iter().cloned().chain(route.middlewares).collect(),
+        ..route
+      })
+      .collect()
+  }
+
+  /// Registers the routes on `tree`, under `prefix`.
+  ///
+  /// # Panics
+  ///
+  /// Panics where `RouteTree::insert` would.
+  pub(crate) fn install(self, prefix: &str, tree: &mut RouteTree) {
+    for route in self.into_routes(prefix) {
+      tree.insert(&route.path, route.rt.clone(), Rh { handler: route.handler });
+      for middleware in route.middlewares {
+        tree.add_route_middleware(&route.path, &route.rt, middleware);
+      }
+      if let Some(max_bytes) = route.max_body {
+        tree.set_body_limit(&route.path, &route.rt, max_bytes);
+      }
+    }
+  }
+
+  fn route_mut(&mut self, path: &str, rt: &Rt) -> &mut RouterRoute {
+    match self.routes.iter_mut().rev().find(|r| r.path == path && r.rt == *rt) {
+      Some(route) => route,
+      None => panic!("no route {} {} in the router", rt, path),
+    }
+  }
+}
+
+/// `prefix` and `path` joined by a single slash; a bare `/` path stands for
+/// the prefix itself.
+fn join_path(prefix: &str, path: &str) -> String {
+  let prefix = prefix.trim_end_matches('/');
+  let path = path.trim_start_matches('/');
+  match (prefix.is_empty(), path.is_empty()) {
+    (true, true) => "/".to_string(),
+    (_, true) => prefix.to_string(),
+    _ => format!("{}/{}", prefix, path),
+  }
+}
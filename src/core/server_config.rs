@@ -0,0 +1,266 @@
+#![cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+
+use crate::core::error_pages::ErrorPages;
+use crate::core::extract::States;
+use crate::core::handler::Handler;
+use crate::core::limits::Limits;
+use crate::core::middleware::Middleware;
+use crate::core::request_type::Rt;
+use crate::core::router::Router;
+use crate::core::status_code::StatusCode;
+use crate::core::timeouts::Timeouts;
+use crate::runtime::shared::{DEFAULT_KEEP_ALIVE_TIMEOUT, DEFAULT_MAX_REQUESTS, DEFAULT_SHUTDOWN_GRACE};
+use crate::{Request, Response, Server};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many worker threads the sync server runs by default.
+pub const DEFAULT_WORKERS: usize = 10;
+
+/// Everything a [`Server`] is set up with, whichever runtime is enabled.
+///
+/// Settings a runtime has no use for are ignored: `workers` only sizes the
+/// sync server's thread pool, and `shutdown_grace` only bounds how long an
+/// async server waits for in-flight requests when stopping.
+#[derive(Clone)]
+pub struct ServerConfig {
+  /// Address to listen on, e.g. `"127.0.0.1:7878"`; port 0 picks a free one.
+  pub addr: String,
+  pub workers: usize,
+  pub auto_close: bool,
+  pub keep_alive_timeout: Duration,
+  pub max_requests: usize,
+  pub limits: Limits,
+  pub timeouts: Timeouts,
+  pub shutdown_grace: Duration,
+  /// Directories static files are served from, in lookup order.
+  pub files_sources: Vec<String>,
+  pub routes: Vec<(String, Rt, Arc<dyn Handler>)>,
+  /// Per-route body size limits, for routes in `routes`.
+  pub body_limits: Vec<(String, Rt, usize)>,
+  pub middlewares: Vec<Arc<dyn Middleware>>,
+  /// Middlewares of the routes under a path prefix.
+  pub group_middlewares: Vec<(String, Arc<dyn Middleware>)>,
+  /// Middlewares of single routes in `routes`.
+  pub route_middlewares: Vec<(String, Rt, Arc<dyn Middleware>)>,
+  /// Answers requests that match no route and no static file.
+  pub fallback: Option<Arc<dyn Handler>>,
+  pub error_pages: ErrorPages,
+  /// State shared by every handler, one value per type.
+  pub states: States,
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    ServerConfig {
+      addr: "127.0.0.1:7878".to_string(),
+      workers: DEFAULT_WORKERS,
+      auto_close: true,
+      keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+      max_requests: DEFAULT_MAX_REQUESTS,
+      limits: Limits::default(),
+      timeouts: Timeouts::default(),
+      shutdown_grace: DEFAULT_SHUTDOWN_GRACE,
+      files_sources: Vec::new(),
+      routes: Vec::new(),
+      body_limits: Vec::new(),
+      middlewares: Vec::new(),
+      group_middlewares: Vec::new(),
+      route_middlewares: Vec::new(),
+      fallback: None,
+      error_pages: ErrorPages::new(),
+      states: States::new(),
+    }
+  }
+}
+
+impl ServerConfig {
+  pub fn builder<S: Into<String>>(addr: S) -> ServerBuilder {
+    ServerBuilder::new(addr)
+  }
+}
+
+/// Fluent constructor for a [`ServerConfig`], finished by
+/// [`ServerBuilder::build`].
+///
+/// ```ignore
+/// let server = ServerBuilder::new("127.0.0.1:7878")
+///   .auto_close(false)
+///   .route("/", Rt::GET, handler!(home))
+///   .files_source("res")
+///   .build()?;
+/// ```
+#[derive(Clone, Default)]
+pub struct ServerBuilder {
+  config: ServerConfig,
+}
+
+impl ServerBuilder {
+  pub fn new<S: Into<String>>(addr: S) -> Self {
+    ServerBuilder {
+      config: ServerConfig {
+        addr: addr.into(),
+        ..ServerConfig::default()
+      },
+    }
+  }
+
+  pub fn addr<S: Into<String>>(mut self, addr: S) -> Self {
+    self.config.addr = addr.into();
+    self
+  }
+
+  /// Sets the number of worker threads of the sync server.
+  pub fn workers(mut self, workers: usize) -> Self {
+    self.config.workers = workers;
+    self
+  }
+
+  pub fn auto_close(mut self, active: bool) -> Self {
+    self.config.auto_close = active;
+    self
+  }
+
+  pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+    self.config.keep_alive_timeout = timeout;
+    self
+  }
+
+  pub fn max_requests(mut self, max: usize) -> Self {
+    self.config.max_requests = max;
+    self
+  }
+
+  pub fn limits(mut self, limits: Limits) -> Self {
+    self.config.limits = limits;
+    self
+  }
+
+  pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+    self.config.timeouts = timeouts;
+    self
+  }
+
+  /// Sets how long an async server waits for in-flight requests when
+  /// stopping.
+  pub fn shutdown_grace(mut self, grace: Duration) -> Self {
+    self.config.shutdown_grace = grace;
+    self
+  }
+
+  /// Adds a directory to serve static files from.
+  pub fn files_source<S: Into<String>>(mut self, base: S) -> Self {
+    self.config.files_sources.push(base.into());
+    self
+  }
+
+  /// Adds a route; see `Server::add_route`.
+  pub fn route<S: Into<String>>(mut self, path: S, rt: Rt, handler: Arc<dyn Handler>) -> Self {
+    self.config.routes.push((path.into(), rt, handler));
+    self
+  }
+
+  /// Adds the routes of `router` with `prefix` in front of their paths.
+  pub fn nest(mut self, prefix: &str, router: Router) -> Self {
+    for route in router.into_routes(prefix) {
+      for middleware in route.middlewares {
+        self.config.route_middlewares.push((route.path.clone(), route.rt.clone(), middleware));
+      }
+      if let Some(max_bytes) = route.max_body {
+        self.config.body_limits.push((route.path.clone(), route.rt.clone(), max_bytes));
+      }
+      self.config.routes.push((route.path, route.rt, route.handler));
+    }
+    self
+  }
+
+  /// Adds the routes of `router` as they are.
+  pub fn merge(self, router: Router) -> Self {
+    self.nest("", router)
+  }
+
+  /// Overrides the body size limit of a route added with
+  /// [`route`](Self::route).
+  pub fn body_limit<S: Into<String>>(mut self, path: S, rt: Rt, max_bytes: usize) -> Self {
+    self.config.body_limits.push((path.into(), rt, max_bytes));
+    self
+  }
+
+  /// Adds a middleware run on every request; see `Server::add_middleware`.
+  pub fn middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+    self.config.middlewares.push(middleware);
+    self
+  }
+
+  /// Adds a middleware run on every route under `prefix`.
+  pub fn group_middleware<S: Into<String>>(mut self, prefix: S, middleware: Arc<dyn Middleware>) -> Self {
+    self.config.group_middlewares.push((prefix.into(), middleware));
+    self
+  }
+
+  /// Adds a middleware to a route added with [`route`](Self::route).
+  pub fn route_middleware<S: Into<String>>(mut self, path: S, rt: Rt, middleware: Arc<dyn Middleware>) -> Self {
+    self.config.route_middlewares.push((path.into(), rt, middleware));
+    self
+  }
+
+  /// Sets the handler answering requests that match no route and no static
+  /// file.
+  pub fn fallback(mut self, handler: Arc<dyn Handler>) -> Self {
+    self.config.fallback = Some(handler);
+    self
+  }
+
+  /// Renders the answers with `status` the server produces itself.
+  pub fn error_page<F>(mut self, status: StatusCode, render: F) -> Self
+  where
+    F: Fn(&Request, StatusCode) -> Response + Send + Sync + 'static,
+  {
+    self.config.error_pages.set(status, render);
+    self
+  }
+
+  /// Renders the error answers without a page of their own.
+  pub fn default_error_page<F>(mut self, render: F) -> Self
+  where
+    F: Fn(&Request, StatusCode) -> Response + Send + Sync + 'static,
+  {
+    self.config.error_pages.set_default(render);
+    self
+  }
+
+  /// Shares `value` with every handler; see `Server::add_state`.
+  pub fn state<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+    self.config.states.insert(value);
+    self
+  }
+
+  /// The configuration built so far.
+  pub fn config(self) -> ServerConfig {
+    self.config
+  }
+
+  /// Binds the address and sets up the server of the enabled runtime.
+  ///
+  /// The same call works for every runtime; with `async_tokio` it must be
+  /// made from within a Tokio runtime.
+  ///
+  /// # Panics
+  ///
+  /// Panics where `Server::add_route`, `Server::set_body_limit` or
+  /// `Server::add_route_middleware` would.
+  pub fn build(self) -> std::io::Result<Server> {
+    Server::with_config(self.config)
+  }
+}
+
+impl From<ServerConfig> for ServerBuilder {
+  fn from(config: ServerConfig) -> Self {
+    ServerBuilder { config }
+  }
+}
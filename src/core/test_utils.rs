@@ -1,15 +1,13 @@
 use std::io::{Read, Write};
 use std::net::TcpStream;
-#[cfg(any(
-  feature = "sync",
-  feature = "async_tokio",
-  feature = "async_smol",
-  feature = "async_std"
-))]
-use std::sync::Once;
-#[allow(unused_imports)]
+use std::sync::OnceLock;
 use std::thread;
 use std::time::Duration;
+#[cfg(all(
+  any(feature = "async_tokio", feature = "async_smol", feature = "async_std"),
+  not(feature = "sync")
+))]
+use std::{future::Future, sync::mpsc};
 
 #[cfg(feature = "sync")]
 use crate::runtime::sync::server::Server;
@@ -29,104 +27,158 @@ use crate::runtime::r#async::smol::Server;
 ))]
 use crate::runtime::r#async::async_std::Server;
 
-pub const SERVER_URL: &str = "127.0.0.1:7878";
 pub const POOL_SIZE: u8 = 10;
-pub const INTERVAL: Duration = Duration::from_millis(250);
-#[cfg(any(
-  feature = "sync",
-  feature = "async_tokio",
-  feature = "async_smol",
-  feature = "async_std"
-))]
-static INIT: Once = Once::new();
+static SERVER_ADDR: OnceLock<String> = OnceLock::new();
 
+/// The address of the server shared by the tests, started by
+/// `setup_test_server`.
+pub fn server_url() -> &'static str {
+  SERVER_ADDR.get().expect("the test server has not been set up")
+}
+
+/// Starts the server shared by the tests, once.
 #[cfg(feature = "sync")]
 pub fn setup_test_server<F>(server_factory: F)
 where
-  F: FnOnce() -> Server + Send + 'static,
+  F: FnOnce() -> Server,
 {
-  INIT.call_once(|| {
-    let server = server_factory();
-    thread::spawn(move || {
-      server.run();
-    });
-    thread::sleep(INTERVAL);
-  });
+  SERVER_ADDR.get_or_init(|| spawn_server(server_factory()));
 }
 
-// async_tokio
-#[cfg(all(feature = "async_tokio", not(feature = "sync")))]
-pub async fn setup_test_server<F, Fut>(server_factory: F)
+/// Runs `server` on a thread of its own and returns the address it listens
+/// on. Bind it to port 0 to get a free one; it is listening already, so
+/// requests can be sent right away.
+#[cfg(feature = "sync")]
+pub fn spawn_server(server: Server) -> String {
+  let addr = server.local_addr().unwrap().to_string();
+  thread::spawn(move || server.run());
+  addr
+}
+
+/// Starts the server shared by the tests, once.
+#[cfg(all(
+  any(feature = "async_tokio", feature = "async_smol", feature = "async_std"),
+  not(feature = "sync")
+))]
+pub fn setup_test_server<F, Fut>(server_factory: F)
 where
   F: FnOnce() -> Fut + Send + 'static,
-  Fut: std::future::Future<Output = Server> + Send + 'static,
+  Fut: Future<Output = Server> + 'static,
 {
-  INIT.call_once(|| {
-    thread::spawn(move || {
-      // Arranca un runtime Tokio en este hilo
-      let rt = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap();
-      rt.block_on(async move {
-        let server = server_factory().await;
-        server.run().await;
-      });
-    });
-    thread::sleep(INTERVAL);
-  });
+  SERVER_ADDR.get_or_init(|| spawn_server(server_factory));
 }
 
-// async_std
+/// Runs the server `server_factory` makes on a thread and runtime of its
+/// own, and returns the address it listens on once it is. Bind it to port 0
+/// to get a free one.
 #[cfg(all(
-  feature = "async_std",
-  not(any(feature = "sync", feature = "async_tokio"))
+  any(feature = "async_tokio", feature = "async_smol", feature = "async_std"),
+  not(feature = "sync")
 ))]
-pub async fn setup_test_server<F, Fut>(server_factory: F)
+pub fn spawn_server<F, Fut>(server_factory: F) -> String
 where
   F: FnOnce() -> Fut + Send + 'static,
-  Fut: std::future::Future<Output = Server> + Send + 'static,
+  Fut: Future<Output = Server> + 'static,
 {
-  INIT.call_once(|| {
-    thread::spawn(move || {
-      // Arranca async-std en este hilo
-      async_std::task::block_on(async move {
-        let server = server_factory().await;
-        server.run().await;
-      });
-    });
-    thread::sleep(INTERVAL);
+  let (sender, receiver) = mpsc::channel();
+  thread::spawn(move || {
+    block_on(async move {
+      let server = server_factory().await;
+      sender.send(server.local_addr().unwrap().to_string()).unwrap();
+      server.run().await;
+    })
   });
+  receiver.recv().expect("the test server failed to start")
+}
+
+// Arranca un runtime Tokio en este hilo
+#[cfg(all(feature = "async_tokio", not(feature = "sync")))]
+fn block_on<F: Future>(future: F) -> F::Output {
+  tokio::runtime::Builder::new_multi_thread()
+    .enable_all()
+    .build()
+    .unwrap()
+    .block_on(future)
 }
 
 #[cfg(all(
   feature = "async_smol",
-  not(any(feature = "sync", feature = "async_tokio", feature = "async_std"))
+  not(any(feature = "sync", feature = "async_tokio"))
 ))]
-pub async fn setup_test_server<F, Fut>(server_factory: F)
-where
-  F: FnOnce() -> Fut + Send + 'static,
-  Fut: std::future::Future<Output = Server> + Send + 'static,
-{
-  INIT.call_once(|| {
-    thread::spawn(move || {
-      smol::block_on(async move {
-        let server = server_factory().await;
-        server.run().await;
-      });
-    });
-    thread::sleep(INTERVAL);
-  });
+use smol::block_on;
+
+#[cfg(all(
+  feature = "async_std",
+  not(any(feature = "sync", feature = "async_tokio", feature = "async_smol"))
+))]
+use async_std::task::block_on;
+
+/// Sends a raw request and returns every byte the server answered with.
+pub fn send_raw(request: &[u8]) -> Vec<u8> {
+  send_raw_to(server_url(), request)
 }
 
-pub fn run_test(request: &[u8], expected_response: &[u8]) -> String {
-  let mut stream = TcpStream::connect(SERVER_URL).expect("Failed to connect to server");
+/// Sends a raw request to the server at `addr` and returns every byte it
+/// answered with.
+pub fn send_raw_to(addr: &str, request: &[u8]) -> Vec<u8> {
+  send_pieces_to(addr, &[request], Duration::ZERO)
+}
+
+/// Sends a raw request to the server at `addr` and returns its answer as
+/// text.
+pub fn send_text_to(addr: &str, request: &[u8]) -> String {
+  String::from_utf8_lossy(&send_raw_to(addr, request)).into_owned()
+}
 
+/// Sends a raw request to the server at `addr` in `pieces`, pausing for
+/// `pause` after each, and returns every byte it answered with. Failures
+/// past connecting are ignored: a server may answer and close before it
+/// has read the whole request.
+pub fn send_pieces_to(addr: &str, pieces: &[&[u8]], pause: Duration) -> Vec<u8> {
+  let mut stream = TcpStream::connect(addr).expect("Failed to connect to server");
+
+  for piece in pieces {
+    let _ = stream.write_all(piece);
+    thread::sleep(pause);
+  }
+  let _ = stream.shutdown(std::net::Shutdown::Write);
+
+  let mut buffer = Vec::new();
+  let _ = stream.read_to_end(&mut buffer);
+  buffer
+}
+
+/// Like [`send_pieces_to`], with the answer as text.
+pub fn send_pieces_text_to(addr: &str, pieces: &[&[u8]], pause: Duration) -> String {
+  String::from_utf8_lossy(&send_pieces_to(addr, pieces, pause)).into_owned()
+}
+
+/// Sends a raw request to the server at `addr` without closing the write
+/// side, as a real client would, and returns what it answered with until it
+/// closed the connection or `wait` passed without a byte.
+pub fn send_keeping_open(addr: &str, request: &[u8], wait: Duration) -> Vec<u8> {
+  let mut stream = TcpStream::connect(addr).expect("Failed to connect to server");
+  stream.set_read_timeout(Some(wait)).unwrap();
   stream.write_all(request).unwrap();
-  stream.shutdown(std::net::Shutdown::Write).unwrap();
 
   let mut buffer = Vec::new();
-  stream.read_to_end(&mut buffer).unwrap();
+  let _ = stream.read_to_end(&mut buffer);
+  buffer
+}
+
+/// Splits a raw response into its header block and its body bytes.
+pub fn split_response(raw: &[u8]) -> (String, Vec<u8>) {
+  match raw.windows(4).position(|w| w == b"\r\n\r\n") {
+    Some(pos) => (
+      String::from_utf8_lossy(&raw[..pos]).to_string(),
+      raw[pos + 4..].to_vec(),
+    ),
+    None => (String::from_utf8_lossy(raw).to_string(), Vec::new()),
+  }
+}
+
+pub fn run_test(request: &[u8], expected_response: &[u8]) -> String {
+  let buffer = send_raw(request);
 
   let buffer_string = String::from_utf8_lossy(&buffer).to_string();
   let expected_response_string = String::from_utf8_lossy(expected_response).to_string();
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+/// How long each phase of an exchange may take; `None` leaves it unbounded.
+///
+/// Running out of time while reading the head or the body is answered with
+/// 408 Request Timeout, and a handler that runs too long with 503 Service
+/// Unavailable; either way the connection is closed afterwards. A response
+/// that can't be written in time just gets its connection dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+  /// Receiving the request line and headers. On a kept-alive connection it
+  /// counts from their first byte, the wait for it being bounded by the
+  /// keep-alive timeout instead; with auto-close it counts from the accept.
+  pub header_read: Option<Duration>,
+  /// Receiving the body, counted from the end of the headers.
+  pub body_read: Option<Duration>,
+  /// Running the handler. The sync server runs timed handlers on a pool as
+  /// large as its worker pool, and a handler past its time keeps a thread of
+  /// it until it returns.
+  pub handler: Option<Duration>,
+  /// Sending the whole response, however slowly the client reads it.
+  pub write: Option<Duration>,
+}
+
+impl Default for Timeouts {
+  fn default() -> Self {
+    Timeouts {
+      header_read: Some(Duration::from_secs(30)),
+      body_read: Some(Duration::from_secs(60)),
+      handler: None,
+      write: Some(Duration::from_secs(30)),
+    }
+  }
+}
+
+#[cfg(feature = "sync")]
+pub use timed_stream::TimedStream;
+
+#[cfg(feature = "sync")]
+mod timed_stream {
+  use std::io::{self, Read, Write};
+  use std::net::TcpStream;
+  use std::time::{Duration, Instant};
+
+  /// A blocking stream whose reads and writes fail with `TimedOut` once a
+  /// deadline has passed, however the bytes trickle through until then.
+  pub struct TimedStream<'a> {
+    stream: &'a TcpStream,
+    deadline: Option<Instant>,
+  }
+
+  impl<'a> TimedStream<'a> {
+    pub fn new(stream: &'a TcpStream) -> Self {
+      TimedStream { stream, deadline: None }
+    }
+
+    /// Starts a new deadline `timeout` from now; `None` removes it.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+      self.deadline = timeout.map(|t| Instant::now() + t);
+    }
+  }
+
+  impl TimedStream<'_> {
+    /// What is left until the deadline; an error once it has passed.
+    fn left(&self) -> io::Result<Option<Duration>> {
+      match self.deadline {
+        Some(deadline) => {
+          let left = deadline.saturating_duration_since(Instant::now());
+          if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+          }
+          Ok(Some(left))
+        }
+        None => Ok(None),
+      }
+    }
+  }
+
+  impl Read for TimedStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      self.stream.set_read_timeout(self.left()?)?;
+      let mut stream = self.stream;
+      stream.read(buf)
+    }
+  }
+
+  impl Write for TimedStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.stream.set_write_timeout(self.left()?)?;
+      let mut stream = self.stream;
+      stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      let mut stream = self.stream;
+      stream.flush()
+    }
+  }
+}
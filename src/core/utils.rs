@@ -56,3 +56,57 @@ pub fn secure_path(base: &Path, req_path: &str) -> Option<PathBuf> {
     None
   }
 }
+
+/// Decodes `%XX` escapes (RFC 3986), and `+` as a space when `plus_as_space`
+/// is set as in `application/x-www-form-urlencoded` query strings. Malformed
+/// escapes are kept literally and invalid UTF-8 is replaced with `U+FFFD`.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> String {
+  let bytes = input.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() => {
+        let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+        out.push(u8::from_str_radix(hex, 16).unwrap());
+        i += 3;
+      }
+      b'+' if plus_as_space => {
+        out.push(b' ');
+        i += 1;
+      }
+      b => {
+        out.push(b);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Splits an `application/x-www-form-urlencoded` string, a query string or
+/// a form body, into decoded pairs in order. Parts without `=` are skipped.
+pub fn parse_urlencoded(input: &str) -> Vec<(String, String)> {
+  input
+    .split('&')
+    .filter_map(|pair| pair.split_once('='))
+    .map(|(key, value)| (percent_decode(key, true), percent_decode(value, true)))
+    .collect()
+}
+
+/// Runs `future` to completion unless `timer` fires first, in which case
+/// `None` is returned. Without a timer the future is simply awaited.
+pub async fn within<F, T>(future: F, timer: Option<T>) -> Option<F::Output>
+where
+  F: std::future::Future,
+  T: std::future::Future,
+{
+  let Some(timer) = timer else {
+    return Some(future.await);
+  };
+  futures::pin_mut!(future, timer);
+  match futures::future::select(future, timer).await {
+    futures::future::Either::Left((output, _)) => Some(output),
+    futures::future::Either::Right(_) => None,
+  }
+}
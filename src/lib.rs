@@ -1,7 +1,18 @@
 pub mod core;
 
 // Common re-exports (always available)
-pub use crate::core::{request_type::Rt, response::Response, status_code::StatusCode, test_utils};
+pub use crate::core::{
+  body::Body,
+  headers::Headers,
+  into_response::IntoResponse,
+  limits::Limits,
+  param::ParamError,
+  request_type::Rt,
+  response::{Response, ResponseBuilder},
+  status_code::StatusCode,
+  test_utils,
+  timeouts::Timeouts,
+};
 
 // Feature-gated re-exports (exist only when any handler feature is enabled)
 #[cfg(any(
@@ -11,9 +22,14 @@ pub use crate::core::{request_type::Rt, response::Response, status_code::StatusC
   feature = "async_smol"
 ))]
 pub use crate::core::{
+  error_pages::{ErrorPages, ErrorRenderer},
+  extract::{FromRequest, Header, Rejection, State, States, TypedHeader},
   handler::Handler,
+  middleware::{Middleware, Next},
   request::Request,
   request_handler::Rh,
+  router::Router,
+  server_config::{ServerBuilder, ServerConfig},
 };
 
 pub mod runtime {
@@ -34,9 +50,26 @@ pub mod runtime {
     pub mod shared;
   }
 
+  pub mod server_core;
   pub mod shared;
 }
 
+#[cfg(feature = "json")]
+pub use crate::core::into_response::Json;
+
+#[cfg(all(
+  feature = "serde",
+  any(
+    feature = "sync",
+    feature = "async_tokio",
+    feature = "async_std",
+    feature = "async_smol"
+  )
+))]
+pub use crate::core::extract::{Form, Path, Query};
+
+pub use runtime::shared::ShutdownHandle;
+
 // Server export selection
 #[cfg(feature = "sync")]
 pub use runtime::sync::server::Server;
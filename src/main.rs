@@ -11,7 +11,7 @@ use {smol::Timer as SmolTimer, std::time::Duration};
   feature = "async_std",
   feature = "async_smol"
 ))]
-use httpageboy::{handler, Request, Response, Rt, Server, StatusCode};
+use httpageboy::{handler, Headers, Request, Response, Rt, Server, StatusCode};
 
 // ROUTE HANDLER
 #[cfg(feature = "sync")]
@@ -23,8 +23,8 @@ fn demo_get(_request: &Request) -> Response {
 <meta charset=\"utf-8\">\
 </head><body>🤓: Hi, this is Pageboy working.
 <br>Do you like the <a href=\"/HTTPageboy.svg\">new icon</a>?</body></html>"
-      .as_bytes()
-      .to_vec(),
+      .into(),
+    headers: Headers::new(),
   }
 }
 
@@ -44,8 +44,8 @@ async fn demo_get(_request: &Request) -> Response {
 <meta charset=\"utf-8\">\
 </head><body>🤓: Hi, this is Pageboy working.
 <br>Do you like the <a href=\"/HTTPageboy.svg\">new icon</a>?</body></html>"
-      .as_bytes()
-      .to_vec(),
+      .into(),
+    headers: Headers::new(),
   }
 }
 
@@ -1,11 +1,20 @@
-use crate::core::handler::Handler;
-use crate::core::request_handler::Rh;
+use crate::core::body::{encode_chunk, read_file_chunk, Body, LAST_CHUNK};
+use crate::core::limits::Limits;
+use crate::core::request::{handle_request_async, Request};
 use crate::core::request_type::Rt;
 use crate::core::response::Response;
+use crate::core::route_tree::RouteTree;
+use crate::core::timeouts::Timeouts;
+use crate::core::utils::within;
+use crate::runtime::server_core::ServerCore;
+use crate::runtime::shared::{build_response_head, handler_timed_out, print_server_info};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use futures::StreamExt;
+use std::future::Future;
 use std::io::Result;
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
 
 /// A trait that abstracts over the different async TCP streams.
 /// This allows us to write generic code that can work with any of the supported runtimes.
@@ -16,26 +25,42 @@ pub trait AsyncStream: Send + Sync {
     async fn shutdown(&mut self) -> Result<()>;
 }
 
+/// The listener of a runtime, along with the few runtime pieces the
+/// connection loop of [`GenericServer`] needs: reading requests off a
+/// buffered connection, a timer and a task spawner.
+#[async_trait]
+pub trait AsyncListener: Send + Sync + 'static {
+    /// An accepted connection, buffered for reading.
+    type Connection: Send + 'static;
+    /// The stream under a connection, responses are written to.
+    type Stream: AsyncStream;
+
+    async fn accept(&self) -> Result<Self::Connection>;
+    fn local_addr(&self) -> Result<SocketAddr>;
+    /// Waits for the next request on `connection`; `false` once the client
+    /// has closed it or it broke.
+    async fn next_request(connection: &mut Self::Connection) -> bool;
+    /// Reads one request off `connection`; see `parse_stream_tokio`.
+    async fn parse(
+        connection: &mut Self::Connection,
+        routes: &RouteTree,
+        limits: &Limits,
+        timeouts: &Timeouts,
+    ) -> (Request, Option<Response>);
+    fn stream(connection: &mut Self::Connection) -> &mut Self::Stream;
+    async fn sleep(duration: Duration);
+    fn spawn<F: Future<Output = ()> + Send + 'static>(future: F);
+}
+
 /// Sends a response to the client over the given stream.
-pub async fn send_response<S: AsyncStream>(stream: &mut S, resp: &Response, close: bool) {
-    let conn_hdr = if close {
-        "Connection: close\r\n"
-    } else {
-        ""
-    };
-    let head = format!(
-        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}\r\n",
-        resp.status,
-        resp.content_type,
-        resp.content.len(),
-        conn_hdr,
-    );
+///
+/// With `head_only` (an answer to HEAD) the header block still announces the
+/// full `Content-Length`, but the body is not written.
+pub async fn send_response<S: AsyncStream>(stream: &mut S, resp: Response, close: bool, head_only: bool) {
+    let head = build_response_head(&resp, close);
     let _ = stream.write_all(head.as_bytes()).await;
-    if resp.content_type.starts_with("image/") {
-        let _ = stream.write_all(&resp.content).await;
-    } else {
-        let text = String::from_utf8_lossy(&resp.content);
-        let _ = stream.write_all(text.as_bytes()).await;
+    if !head_only {
+        let _ = write_body(stream, resp.content).await;
     }
     let _ = stream.flush().await;
     if close {
@@ -43,35 +68,134 @@ pub async fn send_response<S: AsyncStream>(stream: &mut S, resp: &Response, clos
     }
 }
 
+/// Writes a response body piece by piece, chunk-encoding it when its length
+/// is unknown.
+async fn write_body<S: AsyncStream>(stream: &mut S, body: Body) -> Result<()> {
+    match body {
+        Body::Bytes(bytes) => stream.write_all(&bytes).await,
+        Body::File(mut file) => {
+            while let Some(chunk) = read_file_chunk(&mut file)? {
+                stream.write_all(&chunk).await?;
+            }
+            Ok(())
+        }
+        Body::Chunks(chunks) => {
+            for chunk in chunks {
+                stream.write_all(&encode_chunk(&chunk)).await?;
+            }
+            stream.write_all(LAST_CHUNK).await
+        }
+        Body::Stream(mut chunks) => {
+            while let Some(chunk) = chunks.next().await {
+                stream.write_all(&encode_chunk(&chunk)).await?;
+            }
+            stream.write_all(LAST_CHUNK).await
+        }
+    }
+}
+
 /// A generic server implementation that is parameterized over a listener type.
 /// This allows us to share the server logic between the different async runtimes.
+/// Its setters are those of the [`ServerCore`] it dereferences to.
 pub struct GenericServer<L> {
     pub listener: L,
-    pub routes: Arc<HashMap<(Rt, String), Rh>>,
-    pub files_sources: Arc<Vec<String>>,
-    pub auto_close: bool,
+    pub core: ServerCore,
 }
 
-impl<L> GenericServer<L> {
-    /// Toggles the `Connection: close` header.
-    pub fn set_auto_close(&mut self, active: bool) {
-        self.auto_close = active;
+impl<L> Deref for GenericServer<L> {
+    type Target = ServerCore;
+    fn deref(&self) -> &Self::Target {
+        &self.core
     }
+}
 
-    /// Adds a new route to the server.
-    pub fn add_route(&mut self, path: &str, rt: Rt, handler: Arc<dyn Handler>) {
-        Arc::get_mut(&mut self.routes)
-            .unwrap()
-            .insert((rt, path.to_string()), Rh { handler });
+impl<L> DerefMut for GenericServer<L> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.core
+    }
+}
+
+impl<L: AsyncListener> GenericServer<L> {
+    /// The address the server listens on, e.g. the port picked when bound
+    /// to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
     }
 
-    /// Adds a new directory to serve static files from.
-    pub fn add_files_source<S>(&mut self, base: S)
-    where
-        S: Into<String>,
-    {
-        Arc::get_mut(&mut self.files_sources)
-            .unwrap()
-            .push(base.into());
+    /// Starts the server and begins accepting connections.
+    ///
+    /// Unless auto-close is on, each connection keeps serving requests until
+    /// the client sends `Connection: close`, stays idle past the keep-alive
+    /// timeout or reaches the per-connection request limit.
+    ///
+    /// Runs until stopped through [`shutdown_handle`](ServerCore::shutdown_handle).
+    pub async fn run(&self) {
+        self.run_until(std::future::pending()).await
     }
-}
\ No newline at end of file
+
+    /// Like [`run`](Self::run), but also shuts down once `signal` resolves.
+    ///
+    /// Shutting down stops accepting connections and closes each open one
+    /// after its current request; the method returns when they are all done
+    /// or the shutdown grace period is over, leaving any stragglers behind.
+    pub async fn run_until<F: Future<Output = ()>>(&self, signal: F) {
+        print_server_info(self.local_addr().unwrap(), self.auto_close);
+        let (tracker, mut in_flight) = futures::channel::mpsc::channel::<()>(0);
+        let stopped = async {
+            futures::pin_mut!(signal);
+            futures::future::select(signal, Box::pin(self.shutdown.requested())).await;
+        };
+        futures::pin_mut!(stopped);
+        while let Some(accepted) = within(self.listener.accept(), Some(stopped.as_mut())).await {
+            if let Ok(mut connection) = accepted {
+                let routes = self.routes.clone();
+                let sources = self.files_sources.clone();
+                let close_flag = self.auto_close;
+                let idle = self.keep_alive_timeout;
+                let max_requests = self.max_requests;
+                let limits = self.limits;
+                let timeouts = self.timeouts;
+                let stop = self.shutdown.clone();
+                let tracker = tracker.clone();
+
+                L::spawn(async move {
+                    let mut served = 0;
+                    loop {
+                        if !close_flag {
+                            let pending = within(L::next_request(&mut connection), Some(L::sleep(idle)));
+                            if !within(pending, Some(stop.requested())).await.flatten().unwrap_or(false) {
+                                break;
+                            }
+                        }
+                        let (mut req, early) = L::parse(&mut connection, &routes, &limits, &timeouts).await;
+                        served += 1;
+                        let mut close = close_flag || req.closes_connection() || served >= max_requests;
+                        let resp = match early {
+                            Some(r) => routes.error_pages().apply(&req, r),
+                            None => {
+                                let handled = handle_request_async(&mut req, &routes, &sources);
+                                match within(handled, timeouts.handler.map(L::sleep)).await {
+                                    Some(resp) => resp.unwrap_or_else(Response::new),
+                                    None => {
+                                        close = true;
+                                        routes.error_pages().apply(&req, handler_timed_out())
+                                    }
+                                }
+                            }
+                        };
+                        close |= stop.is_shutdown();
+                        let head_only = req.method == Rt::HEAD;
+                        let sending = send_response(L::stream(&mut connection), resp, close, head_only);
+                        if within(sending, timeouts.write.map(L::sleep)).await.is_none() || close {
+                            break;
+                        }
+                    }
+                    drop(tracker);
+                });
+            }
+        }
+        self.shutdown.shutdown();
+        drop(tracker);
+        within(in_flight.next(), Some(L::sleep(self.shutdown_grace))).await;
+    }
+}
@@ -1,13 +1,20 @@
-use crate::core::request::handle_request_async;
+use crate::core::limits::Limits;
+use crate::core::request::{parse_stream_tokio, Request};
 use crate::core::request_handler::Rh;
+use crate::core::request_type::Rt;
 use crate::core::response::Response;
+use crate::core::route_tree::RouteTree;
+use crate::core::server_config::ServerConfig;
+use crate::core::timeouts::Timeouts;
 use super::shared;
-use crate::runtime::shared::print_server_info;
+use crate::runtime::server_core::ServerCore;
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
 
 #[async_trait]
@@ -25,6 +32,46 @@ impl shared::AsyncStream for TcpStream {
     }
 }
 
+#[async_trait]
+impl shared::AsyncListener for TcpListener {
+    type Connection = BufReader<TcpStream>;
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> std::io::Result<Self::Connection> {
+        let (stream, _) = TcpListener::accept(self).await?;
+        Ok(BufReader::new(stream))
+    }
+
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        TcpListener::local_addr(self)
+    }
+
+    async fn next_request(connection: &mut Self::Connection) -> bool {
+        matches!(connection.fill_buf().await, Ok(buf) if !buf.is_empty())
+    }
+
+    async fn parse(
+        connection: &mut Self::Connection,
+        routes: &RouteTree,
+        limits: &Limits,
+        timeouts: &Timeouts,
+    ) -> (Request, Option<Response>) {
+        parse_stream_tokio(connection, routes, limits, timeouts).await
+    }
+
+    fn stream(connection: &mut Self::Connection) -> &mut TcpStream {
+        connection.get_mut()
+    }
+
+    async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    fn spawn<F: Future<Output = ()> + Send + 'static>(future: F) {
+        tokio::spawn(future);
+    }
+}
+
 /// A non-blocking HTTP server powered by Tokio.
 pub struct Server(pub shared::GenericServer<TcpListener>);
 
@@ -45,38 +92,25 @@ impl Server {
     /// Creates a new server and binds to the specified URL.
     pub async fn new(
         serving_url: &str,
-        routes_list: Option<HashMap<(crate::core::request_type::Rt, String), Rh>>,
+        routes_list: Option<HashMap<(Rt, String), Rh>>,
     ) -> std::io::Result<Self> {
         let listener = TcpListener::bind(serving_url).await?;
-        Ok(Server(shared::GenericServer {
-            listener,
-            routes: Arc::new(routes_list.unwrap_or_default()),
-            files_sources: Arc::new(Vec::new()),
-            auto_close: true,
-        }))
+        Ok(Self::from_listener(listener, routes_list))
     }
 
-    /// Starts the server and begins accepting connections.
-    pub async fn run(&self) {
-        print_server_info(self.listener.local_addr().unwrap(), self.auto_close);
-        loop {
-            if let Ok((mut stream, _)) = self.listener.accept().await {
-                let routes = self.routes.clone();
-                let sources = self.files_sources.clone();
-                let close_flag = self.auto_close;
-
-                tokio::spawn(async move {
-                    let (mut req, early) =
-                        crate::core::request::parse_stream_tokio(&mut stream, &routes, &sources).await;
-                    let resp = match early {
-                        Some(r) => r,
-                        None => handle_request_async(&mut req, &routes, &sources)
-                            .await
-                            .unwrap_or_else(Response::new),
-                    };
-                    shared::send_response(&mut stream, &resp, close_flag).await;
-                });
-            }
-        }
+    /// Creates a server set up as `config` says, binding without awaiting.
+    pub fn with_config(config: ServerConfig) -> std::io::Result<Self> {
+        let listener = std::net::TcpListener::bind(&config.addr)?;
+        listener.set_nonblocking(true)?;
+        let mut server = Self::from_listener(TcpListener::from_std(listener)?, None);
+        server.configure(config);
+        Ok(server)
     }
-}
\ No newline at end of file
+
+    fn from_listener(listener: TcpListener, routes_list: Option<HashMap<(Rt, String), Rh>>) -> Self {
+        Server(shared::GenericServer {
+            listener,
+            core: ServerCore::new(routes_list),
+        })
+    }
+}
@@ -1,3 +1,4 @@
+pub mod server_core;
 pub mod shared;
 
 #[cfg(feature = "sync")]
@@ -0,0 +1,251 @@
+#![cfg(any(
+  feature = "sync",
+  feature = "async_tokio",
+  feature = "async_std",
+  feature = "async_smol"
+))]
+
+use crate::core::error_pages::ErrorPages;
+use crate::core::extract::States;
+use crate::core::handler::Handler;
+use crate::core::limits::Limits;
+use crate::core::middleware::Middleware;
+use crate::core::request::Request;
+use crate::core::request_handler::Rh;
+use crate::core::request_type::Rt;
+use crate::core::response::Response;
+use crate::core::route_tree::RouteTree;
+use crate::core::router::Router;
+use crate::core::server_config::ServerConfig;
+use crate::core::status_code::StatusCode;
+use crate::core::timeouts::Timeouts;
+use crate::runtime::shared::{ShutdownHandle, DEFAULT_KEEP_ALIVE_TIMEOUT, DEFAULT_MAX_REQUESTS, DEFAULT_SHUTDOWN_GRACE};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What every server is set up with, whichever runtime runs it: the routes
+/// and everything attached to them, the static file directories and the
+/// connection settings. Each runtime's server dereferences to it, so the
+/// setters below are shared by all of them.
+pub struct ServerCore {
+  pub routes: Arc<RouteTree>,
+  pub files_sources: Arc<Vec<String>>,
+  pub auto_close: bool,
+  pub keep_alive_timeout: Duration,
+  pub max_requests: usize,
+  pub limits: Limits,
+  pub timeouts: Timeouts,
+  pub shutdown: ShutdownHandle,
+  pub shutdown_grace: Duration,
+}
+
+impl ServerCore {
+  pub(crate) fn new(routes_list: Option<HashMap<(Rt, String), Rh>>) -> Self {
+    ServerCore {
+      routes: Arc::new(RouteTree::from(routes_list.unwrap_or_default())),
+      files_sources: Arc::new(Vec::new()),
+      auto_close: true,
+      keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+      max_requests: DEFAULT_MAX_REQUESTS,
+      limits: Limits::default(),
+      timeouts: Timeouts::default(),
+      shutdown: ShutdownHandle::new(),
+      shutdown_grace: DEFAULT_SHUTDOWN_GRACE,
+    }
+  }
+
+  /// Applies everything in `config` but the address and worker count,
+  /// which the runtime's constructor takes care of.
+  pub(crate) fn configure(&mut self, config: ServerConfig) {
+    self.auto_close = config.auto_close;
+    self.keep_alive_timeout = config.keep_alive_timeout;
+    self.max_requests = config.max_requests;
+    self.limits = config.limits;
+    self.timeouts = config.timeouts;
+    self.shutdown_grace = config.shutdown_grace;
+    for base in config.files_sources {
+      self.add_files_source(base);
+    }
+    for (path, rt, handler) in config.routes {
+      self.add_route(&path, rt, handler);
+    }
+    for (path, rt, max_bytes) in config.body_limits {
+      self.set_body_limit(&path, rt, max_bytes);
+    }
+    if let Some(handler) = config.fallback {
+      self.set_fallback(handler);
+    }
+    self.set_error_pages(config.error_pages);
+    self.set_states(config.states);
+    for middleware in config.middlewares {
+      self.add_middleware(middleware);
+    }
+    for (prefix, middleware) in config.group_middlewares {
+      self.add_group_middleware(&prefix, middleware);
+    }
+    for (path, rt, middleware) in config.route_middlewares {
+      self.add_route_middleware(&path, rt, middleware);
+    }
+  }
+
+  /// Closes every connection after its first response when `true` (the
+  /// default); otherwise connections are kept open for further requests.
+  pub fn set_auto_close(&mut self, active: bool) {
+    self.auto_close = active;
+  }
+
+  /// Sets how long a kept-alive connection may wait for its next request.
+  pub fn set_keep_alive_timeout(&mut self, timeout: Duration) {
+    self.keep_alive_timeout = timeout;
+  }
+
+  /// Sets how many requests one connection may carry before it is closed.
+  pub fn set_max_requests(&mut self, max: usize) {
+    self.max_requests = max;
+  }
+
+  /// Sets the header and body size limits applied to every request.
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.limits = limits;
+  }
+
+  /// Sets the read, handler and write timeouts applied to every request.
+  pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+    self.timeouts = timeouts;
+  }
+
+  /// Sets how long a stopping async server waits for in-flight requests
+  /// before its run method returns anyway. The sync server waits for them
+  /// all.
+  pub fn set_shutdown_grace(&mut self, grace: Duration) {
+    self.shutdown_grace = grace;
+  }
+
+  /// A handle that stops the server from anywhere, e.g. a signal handler.
+  pub fn shutdown_handle(&self) -> ShutdownHandle {
+    self.shutdown.clone()
+  }
+
+  /// Registers a handler for `path` and method `rt`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `path` is ambiguous with a route already registered for `rt`,
+  /// or if it has a catch-all segment (`{*name}` or `*`) before its end.
+  pub fn add_route(&mut self, path: &str, rt: Rt, handler: Arc<dyn Handler>) {
+    self.routes_mut().insert(path, rt, Rh { handler });
+  }
+
+  /// Registers the routes of `router` with `prefix` in front of their paths.
+  ///
+  /// # Panics
+  ///
+  /// Panics where [`add_route`](Self::add_route) would.
+  pub fn nest(&mut self, prefix: &str, router: Router) {
+    router.install(prefix, self.routes_mut());
+  }
+
+  /// Registers the routes of `router` as they are.
+  ///
+  /// # Panics
+  ///
+  /// Panics where [`add_route`](Self::add_route) would.
+  pub fn merge(&mut self, router: Router) {
+    self.nest("", router);
+  }
+
+  /// Overrides the body size limit for the route registered on `path` and
+  /// method `rt`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if no such route has been added.
+  pub fn set_body_limit(&mut self, path: &str, rt: Rt, max_bytes: usize) {
+    if !self.routes_mut().set_body_limit(path, &rt, max_bytes) {
+      panic!("no route {} {} to set a body limit on", rt, path);
+    }
+  }
+
+  /// Adds a middleware run on every request, routed or not.
+  pub fn add_middleware(&mut self, middleware: Arc<dyn Middleware>) {
+    self.routes_mut().add_middleware(middleware);
+  }
+
+  /// Adds a middleware run on every route whose pattern starts with
+  /// `prefix`, e.g. `/api` for `/api/users/{id}`.
+  pub fn add_group_middleware(&mut self, prefix: &str, middleware: Arc<dyn Middleware>) {
+    self.routes_mut().add_group_middleware(prefix, middleware);
+  }
+
+  /// Adds a middleware to the route registered on `path` and method `rt`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if no such route has been added.
+  pub fn add_route_middleware(&mut self, path: &str, rt: Rt, middleware: Arc<dyn Middleware>) {
+    if !self.routes_mut().add_route_middleware(path, &rt, middleware) {
+      panic!("no route {} {} to add a middleware to", rt, path);
+    }
+  }
+
+  /// Sets the handler answering requests that match no route and no static
+  /// file, in place of the default 404.
+  pub fn set_fallback(&mut self, handler: Arc<dyn Handler>) {
+    self.routes_mut().set_fallback(handler);
+  }
+
+  /// Renders the answers with `status` the server produces itself, e.g. a
+  /// 405 for a path routed under other methods or a 400 for a malformed
+  /// request. See [`ErrorPages`].
+  pub fn set_error_page<F>(&mut self, status: StatusCode, render: F)
+  where
+    F: Fn(&Request, StatusCode) -> Response + Send + Sync + 'static,
+  {
+    self.routes_mut().error_pages_mut().set(status, render);
+  }
+
+  /// Renders the error answers without a page of their own.
+  pub fn set_default_error_page<F>(&mut self, render: F)
+  where
+    F: Fn(&Request, StatusCode) -> Response + Send + Sync + 'static,
+  {
+    self.routes_mut().error_pages_mut().set_default(render);
+  }
+
+  /// Replaces every error page at once.
+  pub fn set_error_pages(&mut self, pages: ErrorPages) {
+    *self.routes_mut().error_pages_mut() = pages;
+  }
+
+  /// Shares `value` with every handler, through the
+  /// [`State`](crate::State) extractor or [`Request::state`]. A later value
+  /// of the same type replaces it.
+  pub fn add_state<T: Send + Sync + 'static>(&mut self, value: T) {
+    self.routes_mut().states_mut().insert(value);
+  }
+
+  /// Replaces every shared state at once.
+  pub fn set_states(&mut self, states: States) {
+    *self.routes_mut().states_mut() = states;
+  }
+
+  /// Adds a directory to serve static files from.
+  pub fn add_files_source<S>(&mut self, base: S)
+  where
+    S: Into<String>,
+  {
+    let s = base.into();
+    let canonical = PathBuf::from(&s)
+      .canonicalize()
+      .map(|p| p.to_string_lossy().to_string())
+      .unwrap_or(s.clone());
+    Arc::get_mut(&mut self.files_sources).unwrap().push(canonical);
+  }
+
+  /// The routes, while the server is still being set up.
+  fn routes_mut(&mut self) -> &mut RouteTree {
+    Arc::get_mut(&mut self.routes).unwrap()
+  }
+}
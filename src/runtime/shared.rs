@@ -1,3 +1,91 @@
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long a kept-alive connection may sit idle before it is closed.
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many requests a single connection may carry before it is closed.
+pub const DEFAULT_MAX_REQUESTS: usize = 100;
+/// How long a stopping server waits for in-flight requests to finish.
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+/// Asks a running server to stop. Clones control the same server.
+///
+/// Once triggered the server accepts no new connections, answers the
+/// requests already in flight with `Connection: close`, and returns from
+/// its run method when they are done (an async server gives up on them
+/// after its shutdown grace period).
+#[derive(Clone)]
+pub struct ShutdownHandle {
+  state: Arc<ShutdownState>,
+}
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+struct ShutdownState {
+  requested: AtomicBool,
+  trigger: Mutex<Option<oneshot::Sender<()>>>,
+  signal: Shared<oneshot::Receiver<()>>,
+  hooks: Mutex<Vec<Hook>>,
+}
+
+impl ShutdownHandle {
+  pub fn new() -> Self {
+    let (trigger, signal) = oneshot::channel();
+    ShutdownHandle {
+      state: Arc::new(ShutdownState {
+        requested: AtomicBool::new(false),
+        trigger: Mutex::new(Some(trigger)),
+        signal: signal.shared(),
+        hooks: Mutex::new(Vec::new()),
+      }),
+    }
+  }
+
+  /// Starts the shutdown. Calling it again has no further effect.
+  pub fn shutdown(&self) {
+    self.state.requested.store(true, Ordering::SeqCst);
+    if let Some(trigger) = self.state.trigger.lock().unwrap().take() {
+      let _ = trigger.send(());
+    }
+    let hooks = std::mem::take(&mut *self.state.hooks.lock().unwrap());
+    for hook in hooks {
+      hook();
+    }
+  }
+
+  /// Registers `hook` to run when the shutdown starts, or right away if it
+  /// already has. Lets a blocking server wake itself up.
+  #[cfg(feature = "sync")]
+  pub(crate) fn on_shutdown<F: FnOnce() + Send + 'static>(&self, hook: F) {
+    let mut hooks = self.state.hooks.lock().unwrap();
+    if self.is_shutdown() {
+      drop(hooks);
+      hook();
+    } else {
+      hooks.push(Box::new(hook));
+    }
+  }
+
+  /// Whether the shutdown has been started.
+  pub fn is_shutdown(&self) -> bool {
+    self.state.requested.load(Ordering::SeqCst)
+  }
+
+  /// Resolves once the shutdown has been started.
+  pub async fn requested(&self) {
+    let _ = self.state.signal.clone().await;
+  }
+}
+
+impl Default for ShutdownHandle {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 pub fn print_server_info(addr: std::net::SocketAddr, _auto_close: bool) {
   // println!("Connection autoclose set to {:?}", _auto_close);
 
@@ -16,3 +104,49 @@ pub fn print_server_info(addr: std::net::SocketAddr, _auto_close: bool) {
   #[cfg(feature = "async_smol")]
   println!("Serving (async_smol) on {}", _green_url);
 }
+
+/// The answer sent when a handler runs past its timeout.
+pub fn handler_timed_out() -> crate::core::response::Response {
+  crate::core::response::Response::builder()
+    .status(crate::core::status_code::StatusCode::ServiceUnavailable)
+    .body(Vec::new())
+}
+
+/// Serializes the status line and header block of a response.
+///
+/// `Content-Type`, `Content-Length` and `Connection` are derived from the
+/// response unless its `headers` already set them; `Content-Type` is left out
+/// when the response has none. A body of unknown length gets
+/// `Transfer-Encoding: chunked` instead of `Content-Length`. 1xx, 204 and
+/// 304 answers get neither since they never carry a body. Fields containing
+/// CR or LF are dropped so a handler can't split the response.
+pub fn build_response_head(resp: &crate::core::response::Response, close: bool) -> String {
+  let mut head = format!("HTTP/1.1 {}\r\n", resp.status);
+  if !resp.content_type.is_empty() && !resp.headers.contains("Content-Type") {
+    head.push_str(&format!("Content-Type: {}\r\n", resp.content_type));
+  }
+  let bodiless = resp.status.starts_with('1') || resp.status.starts_with("204") || resp.status.starts_with("304");
+  if !bodiless {
+    match resp.content.len() {
+      Some(length) if !resp.headers.contains("Content-Length") => {
+        head.push_str(&format!("Content-Length: {}\r\n", length));
+      }
+      None if !resp.headers.contains("Transfer-Encoding") => {
+        head.push_str("Transfer-Encoding: chunked\r\n");
+      }
+      _ => {}
+    }
+  }
+  if close && !resp.headers.contains("Connection") {
+    head.push_str("Connection: close\r\n");
+  }
+  for (name, value) in resp.headers.iter() {
+    let unsafe_field = |s: &str| s.contains(['\r', '\n']);
+    if name.is_empty() || unsafe_field(name) || unsafe_field(value) {
+      continue;
+    }
+    head.push_str(&format!("{}: {}\r\n", name, value));
+  }
+  head.push_str("\r\n");
+  head
+}
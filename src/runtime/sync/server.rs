@@ -1,25 +1,48 @@
 #![cfg(feature = "sync")]
 
-use crate::core::handler::Handler;
 use crate::core::request::{handle_request_sync, Request};
 use crate::core::request_handler::Rh;
 use crate::core::request_type::Rt;
 use crate::core::response::Response;
-use crate::runtime::shared::print_server_info;
+use crate::core::route_tree::RouteTree;
+use crate::core::server_config::ServerConfig;
+use crate::core::timeouts::TimedStream;
+use crate::runtime::server_core::ServerCore;
+use crate::runtime::shared::{build_response_head, handler_timed_out, print_server_info, ShutdownHandle};
 use crate::runtime::sync::threadpool::ThreadPool;
 use std::collections::HashMap;
 use std::io::prelude::Write;
-use std::net::{Shutdown, TcpListener, TcpStream};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::ops::{Deref, DerefMut};
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often a connection waiting for its next request checks for shutdown.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
 
 pub struct Server {
   listener: TcpListener,
   pool: Arc<Mutex<ThreadPool>>,
-  routes: HashMap<(Rt, String), Rh>,
-  files_sources: Vec<String>,
-  auto_close: bool,
+  /// Runs handlers when a handler timeout is set, as many at once as there
+  /// are connection workers.
+  handler_pool: Arc<Mutex<ThreadPool>>,
+  core: ServerCore,
+}
+
+impl Deref for Server {
+  type Target = ServerCore;
+  fn deref(&self) -> &Self::Target {
+    &self.core
+  }
+}
+
+impl DerefMut for Server {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.core
+  }
 }
 
 impl Server {
@@ -27,61 +50,97 @@ impl Server {
     serving_url: &str,
     pool_size: u8,
     routes_list: Option<HashMap<(Rt, String), Rh>>,
+  ) -> Result<Server, std::io::Error> {
+    Self::bind(serving_url, pool_size as usize, routes_list)
+  }
+
+  /// Creates a server set up as `config` says.
+  pub fn with_config(config: ServerConfig) -> Result<Server, std::io::Error> {
+    let mut server = Self::bind(&config.addr, config.workers, None)?;
+    server.configure(config);
+    Ok(server)
+  }
+
+  fn bind(
+    serving_url: &str,
+    workers: usize,
+    routes_list: Option<HashMap<(Rt, String), Rh>>,
   ) -> Result<Server, std::io::Error> {
     let listener = TcpListener::bind(serving_url)?;
-    let pool = Arc::new(Mutex::new(ThreadPool::new(pool_size as usize)));
-    let routes = routes_list.unwrap_or_default();
+    let pool = Arc::new(Mutex::new(ThreadPool::new(workers)));
+    let handler_pool = Arc::new(Mutex::new(ThreadPool::new(workers)));
 
     Ok(Server {
       listener,
       pool,
-      routes,
-      files_sources: Vec::new(),
-      auto_close: true,
+      handler_pool,
+      core: ServerCore::new(routes_list),
     })
   }
 
-  pub fn set_auto_close(&mut self, state: bool) {
-    self.auto_close = state;
-  }
-
-  pub fn add_route(&mut self, path: &str, rt: Rt, handler: Arc<dyn Handler>) {
-    let key = (rt, path.to_string());
-    self.routes.insert(key, Rh { handler });
-  }
-
-  pub fn add_files_source<S>(&mut self, base: S)
-  where
-    S: Into<String>,
-  {
-    let s = base.into();
-    let canonical = PathBuf::from(&s)
-      .canonicalize()
-      .map(|p| p.to_string_lossy().to_string())
-      .unwrap_or(s.clone());
-    self.files_sources.push(canonical);
+  /// The address the server listens on, e.g. the port picked when bound to
+  /// port 0.
+  pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+    self.listener.local_addr()
   }
 
+  /// Accepts connections and serves them on the thread pool.
+  ///
+  /// Unless auto-close is on, each connection keeps serving requests until
+  /// the client sends `Connection: close`, stays idle past the keep-alive
+  /// timeout or reaches the per-connection request limit.
+  ///
+  /// Returns once the server is stopped, through [`stop`](Self::stop) or a
+  /// [`shutdown_handle`](ServerCore::shutdown_handle), and every connection it
+  /// accepted has been served. It can't be run again afterwards.
   pub fn run(&self) {
-    print_server_info(self.listener.local_addr().unwrap(), self.auto_close);
+    let addr = self.listener.local_addr().unwrap();
+    print_server_info(addr, self.auto_close);
+    // The accept below blocks, so the shutdown wakes it with a connection
+    // of its own.
+    self.shutdown.on_shutdown(move || {
+      let _ = TcpStream::connect(Self::reachable(addr));
+    });
     for stream in self.listener.incoming() {
+      if self.shutdown.is_shutdown() {
+        break;
+      }
       match stream {
         Ok(stream) => {
           let routes_local = self.routes.clone();
           let sources_local = self.files_sources.clone();
           let close_flag = self.auto_close;
+          let idle = self.keep_alive_timeout;
+          let max_requests = self.max_requests;
+          let limits = self.limits;
+          let timeouts = self.timeouts;
+          let stop = self.shutdown.clone();
+          let handler_pool = Arc::clone(&self.handler_pool);
           let pool = Arc::clone(&self.pool);
           pool.lock().unwrap().run(move || {
-            let (mut request, early_resp) =
-              Request::parse_stream_sync(&stream, &routes_local, &sources_local);
-            let answer = if let Some(resp) = early_resp {
-              Some(resp)
-            } else {
-              handle_request_sync(&mut request, &routes_local, &sources_local)
-            };
-            match answer {
-              Some(response) => Self::send_response(stream, &response, close_flag),
-              None => Self::send_response(stream, &Response::new(), close_flag),
+            let mut reader = BufReader::new(TimedStream::new(&stream));
+            let mut served = 0;
+            loop {
+              if !close_flag && !Self::await_next_request(&mut reader, idle, &stop) {
+                break;
+              }
+              let (mut request, early_resp) =
+                Request::parse_stream_sync(&mut reader, &routes_local, &limits, &timeouts);
+              served += 1;
+              let mut close = close_flag || request.closes_connection() || served >= max_requests;
+              let head_only = request.method == Rt::HEAD;
+              let answer = match early_resp {
+                Some(resp) => routes_local.error_pages().apply(&request, resp),
+                None => Self::handle_timed(&mut request, &routes_local, &sources_local, &handler_pool, timeouts.handler)
+                  .unwrap_or_else(|| {
+                    close = true;
+                    routes_local.error_pages().apply(&request, handler_timed_out())
+                  }),
+              };
+              close |= stop.is_shutdown();
+              if Self::send_response(&stream, answer, close, head_only, timeouts.write).is_err() || close {
+                break;
+              }
             }
           });
         }
@@ -90,34 +149,94 @@ impl Server {
         }
       }
     }
+    // Workers finish the connections already queued before terminating.
+    self.pool.lock().unwrap().stop();
   }
 
+  /// Stops the server: [`run`](Self::run) accepts no more connections,
+  /// closes the open ones after their current request and returns.
   pub fn stop(&self) {
-    let mut pool = self.pool.lock().unwrap();
-    pool.stop();
+    self.shutdown.shutdown();
+  }
+
+  /// Waits up to `idle` for the next request on a kept-alive connection,
+  /// giving up early when the server is stopped.
+  fn await_next_request(reader: &mut BufReader<TimedStream<'_>>, idle: Duration, stop: &ShutdownHandle) -> bool {
+    let deadline = Instant::now() + idle;
+    loop {
+      let left = deadline.saturating_duration_since(Instant::now());
+      if left.is_zero() || stop.is_shutdown() {
+        return false;
+      }
+      reader.get_mut().set_timeout(Some(left.min(SHUTDOWN_POLL)));
+      match reader.fill_buf() {
+        Ok(buf) => return !buf.is_empty(),
+        Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => continue,
+        Err(_) => return false,
+      }
+    }
   }
 
-  fn send_response(mut stream: TcpStream, response: &Response, close: bool) {
-    let connection_header = if close { "Connection: close\r\n" } else { "" };
-    let header = format!(
-      "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}\r\n",
-      response.status,
-      response.content_type,
-      response.content.len(),
-      connection_header
-    );
-    let _ = stream.write_all(header.as_bytes());
-
-    if response.content_type.starts_with("image/") {
-      let _ = stream.write_all(&response.content);
-    } else {
-      let text = String::from_utf8_lossy(&response.content);
-      let _ = stream.write_all(text.as_bytes());
+  /// An address the server can connect to itself on, in place of a
+  /// wildcard one.
+  fn reachable(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+      IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), addr.port()),
+      IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), addr.port()),
+      _ => addr,
     }
+  }
+
+  /// Runs the handler for `request`, giving up after `timeout` with `None`.
+  ///
+  /// A blocking handler can't be interrupted, so with a timeout it runs on
+  /// `pool`, over a copy of the request, and is left to finish in the
+  /// background with its answer dropped. The timeout counts from when the
+  /// request is queued: while every thread of the pool is held by a slow
+  /// handler, waiting requests time out instead of adding threads.
+  fn handle_timed(
+    request: &mut Request,
+    routes: &Arc<RouteTree>,
+    sources: &[String],
+    pool: &Mutex<ThreadPool>,
+    timeout: Option<Duration>,
+  ) -> Option<Response> {
+    let Some(timeout) = timeout else {
+      return Some(handle_request_sync(request, routes, sources).unwrap_or_default());
+    };
+    let mut request = request.clone();
+    let (sender, receiver) = mpsc::channel();
+    let routes = Arc::clone(routes);
+    let sources = sources.to_vec();
+    pool.lock().unwrap().run(move || {
+      let _ = sender.send(handle_request_sync(&mut request, &routes, &sources).unwrap_or_default());
+    });
+    receiver.recv_timeout(timeout).ok()
+  }
+
+  /// Writes `response` out, giving up once `timeout` has passed since the
+  /// first byte.
+  fn send_response(
+    stream: &TcpStream,
+    response: Response,
+    close: bool,
+    head_only: bool,
+    timeout: Option<Duration>,
+  ) -> std::io::Result<()> {
+    let header = build_response_head(&response, close);
+    let mut out = TimedStream::new(stream);
+    out.set_timeout(timeout);
+    let sent = out
+      .write_all(header.as_bytes())
+      .and_then(|_| match head_only {
+        true => Ok(()),
+        false => response.content.write_to(&mut out),
+      })
+      .and_then(|_| out.flush());
 
-    let _ = stream.flush();
     if close {
       let _ = stream.shutdown(Shutdown::Both);
     }
+    sent
   }
 }
@@ -1,12 +1,20 @@
 #![cfg(feature = "sync")]
-use httpageboy::test_utils::{run_test, setup_test_server, POOL_SIZE, SERVER_URL};
-use httpageboy::{handler, Request, Response, Rt, Server, StatusCode};
+use httpageboy::core::middleware::{after, around, before};
+use httpageboy::test_utils::{run_test, send_keeping_open, send_pieces_text_to, send_raw, send_raw_to, send_text_to, server_url, setup_test_server, spawn_server, split_response, POOL_SIZE};
+#[cfg(feature = "json")]
+use httpageboy::{core::extract::UserAgent, Form, Header, Json, Path, Query};
+use httpageboy::{handler, Body, Headers, IntoResponse, Limits, Request, Response, Router, Rt, Server, ServerBuilder, State, StatusCode, Timeouts};
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::io::{Read, Write};
 
 fn create_test_server() -> Server {
-  let mut server = Server::new(SERVER_URL, POOL_SIZE, None).unwrap();
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
 
   server.add_route("/", Rt::GET, handler!(demo_handle_home));
+  server.add_route("/closure/empty", Rt::GET, handler!(|_req| Response::new()));
+  server.add_route("/closure/path", Rt::GET, handler!(|req| format!("closure {}", req.path)));
   server.add_route("/test", Rt::GET, handler!(demo_handle_get));
   server.add_route("/test", Rt::POST, handler!(demo_handle_post));
   server.add_route("/test/{param1}", Rt::POST, handler!(demo_handle_post));
@@ -17,6 +25,26 @@ fn create_test_server() -> Server {
   );
   server.add_route("/test", Rt::PUT, handler!(demo_handle_put));
   server.add_route("/test", Rt::DELETE, handler!(demo_handle_delete));
+  server.add_route("/headers", Rt::GET, handler!(demo_handle_headers));
+  server.add_route("/binary", Rt::GET, handler!(demo_handle_binary));
+  server.add_route("/echo", Rt::POST, handler!(demo_handle_echo));
+  server.add_route("/overlap/{name}", Rt::GET, handler!(demo_handle_overlap_param));
+  server.add_route("/overlap/static", Rt::GET, handler!(demo_handle_overlap_static));
+  server.add_route("/files/{*rest}", Rt::GET, handler!(demo_handle_catch_all));
+  server.add_route("/api/*", Rt::GET, handler!(demo_handle_catch_all));
+  server.add_route("/items/{id:u64}", Rt::GET, handler!(demo_handle_item_id));
+  server.add_route("/items/{slug:[a-z-]+}", Rt::GET, handler!(demo_handle_item_slug));
+  server.add_route("/items/{uuid:uuid}", Rt::GET, handler!(demo_handle_item_uuid));
+  server.add_route("/typed/{value}", Rt::GET, handler!(demo_handle_typed));
+  server.add_route("/test", Rt::PATCH, handler!(demo_handle_patch));
+  server.add_route("/dav", Rt::Extension("PROPFIND".to_string()), handler!(demo_handle_propfind));
+  server.add_route("/raw/{name}", Rt::GET, handler!(demo_handle_raw));
+  server.add_route("/split/{id}", Rt::GET, handler!(demo_handle_split));
+  server.add_route("/inspect", Rt::GET, handler!(demo_handle_inspect));
+  server.add_route("/stream", Rt::GET, handler!(demo_handle_stream));
+  server.add_route("/codes/{code:[a-z]*[a-z]*[a-z]*[a-z]*[a-z]*[a-z]*[a-z]*[a-z]*0}", Rt::GET, handler!(demo_handle_code));
+  server.add_route("/order/{id:u64}", Rt::GET, handler!(demo_handle_order_id));
+  server.add_route("/order/{n:[0-9]+}", Rt::GET, handler!(demo_handle_order_digits));
   let res_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("res");
   server.add_files_source(res_path.to_str().unwrap());
 
@@ -27,7 +55,8 @@ fn demo_handle_home(_request: &Request) -> Response {
   Response {
     status: StatusCode::Ok.to_string(),
     content_type: String::new(),
-    content: "home".as_bytes().to_vec(),
+    content: "home".into(),
+    headers: Headers::new(),
   }
 }
 
@@ -43,7 +72,8 @@ fn demo_handle_get(_request: &Request) -> Response {
   Response {
     status: StatusCode::Ok.to_string(),
     content_type: String::new(),
-    content: "get".as_bytes().to_vec(),
+    content: "get".into(),
+    headers: Headers::new(),
   }
 }
 
@@ -72,20 +102,21 @@ fn demo_handle_post(_request: &Request) -> Response {
 
   let request_string = format!(
     "Method: {}\nUri: {}\nParams: {:?}\nBody: {:?}",
-    _request.method, _request.path, ordered, _request.body
+    _request.method, _request.path, ordered, _request.body_str()
   );
 
   Response {
     status: StatusCode::Ok.to_string(),
     content_type: String::new(),
-    content: request_string.as_bytes().to_vec(),
+    content: request_string.into(),
+    headers: Headers::new(),
   }
 }
 
 #[test]
 fn test_post() {
   setup_test_server(create_test_server);
-  let request = b"POST /test HTTP/1.1\r\n\r\nmueve tu cuerpo";
+  let request = b"POST /test HTTP/1.1\r\nContent-Length: 15\r\n\r\nmueve tu cuerpo";
   let expected_response = b"Method: POST\nUri: /test\nParams: {}\nBody: \"mueve tu cuerpo\"";
   run_test(request, expected_response);
 }
@@ -93,7 +124,7 @@ fn test_post() {
 #[test]
 fn test_post_with_query() {
   setup_test_server(create_test_server);
-  let request = b"POST /test?foo=bar HTTP/1.1\r\n\r\nmueve tu cuerpo";
+  let request = b"POST /test?foo=bar HTTP/1.1\r\nContent-Length: 15\r\n\r\nmueve tu cuerpo";
   let expected_response = b"Method: POST\nUri: /test\nParams: {\"foo\": \"bar\"}\nBody: \"mueve tu cuerpo\"";
   run_test(request, expected_response);
 }
@@ -109,7 +140,7 @@ fn test_post_with_content_length() {
 #[test]
 fn test_post_with_params() {
   setup_test_server(create_test_server);
-  let request = b"POST /test/hola/que?param4=hoy&param3=hace HTTP/1.1\r\n\r\nmueve tu cuerpo";
+  let request = b"POST /test/hola/que?param4=hoy&param3=hace HTTP/1.1\r\nContent-Length: 15\r\n\r\nmueve tu cuerpo";
   let expected_response = b"Method: POST\n\
       Uri: /test/hola/que\n\
       Params: {\"param1\": \"hola\", \"param2\": \"que\", \"param3\": \"hace\", \"param4\": \"hoy\"}\n\
@@ -120,7 +151,7 @@ fn test_post_with_params() {
 #[test]
 fn test_post_with_incomplete_path_params() {
   setup_test_server(create_test_server);
-  let request = b"POST /test/hola HTTP/1.1\r\n\r\nmueve tu cuerpo";
+  let request = b"POST /test/hola HTTP/1.1\r\nContent-Length: 15\r\n\r\nmueve tu cuerpo";
   let expected_response = b"Method: POST\nUri: /test/hola\nParams: {\"param1\": \"hola\"}\nBody: \"mueve tu cuerpo\"";
   run_test(request, expected_response);
 }
@@ -128,19 +159,20 @@ fn test_post_with_incomplete_path_params() {
 fn demo_handle_put(_request: &Request) -> Response {
   let request_string = format!(
     "Method: {}\nUri: {}\nParams: {:?}\nBody: {:?}",
-    _request.method, _request.path, _request.params, _request.body
+    _request.method, _request.path, _request.params, _request.body_str()
   );
   Response {
     status: StatusCode::Ok.to_string(),
     content_type: String::new(),
-    content: request_string.as_bytes().to_vec(),
+    content: request_string.into(),
+    headers: Headers::new(),
   }
 }
 
 #[test]
 fn test_put() {
   setup_test_server(create_test_server);
-  let request = b"PUT /test HTTP/1.1\r\n\r\nmueve tu cuerpo";
+  let request = b"PUT /test HTTP/1.1\r\nContent-Length: 15\r\n\r\nmueve tu cuerpo";
   let expected_response = b"Method: PUT\nUri: /test\nParams: {}\nBody: \"mueve tu cuerpo\"";
   run_test(request, expected_response);
 }
@@ -149,7 +181,8 @@ fn demo_handle_delete(_request: &Request) -> Response {
   Response {
     status: StatusCode::Ok.to_string(),
     content_type: String::new(),
-    content: "delete".as_bytes().to_vec(),
+    content: "delete".into(),
+    headers: Headers::new(),
   }
 }
 
@@ -178,10 +211,10 @@ fn test_file_not_found() {
 }
 
 #[test]
-fn test_method_not_allowed() {
+fn test_unknown_method_not_implemented() {
   setup_test_server(create_test_server);
   let request = b"BREW /coffee HTTP/1.1\r\n\r\n";
-  let expected_response = b"HTTP/1.1 405 Method Not Allowed";
+  let expected_response = b"HTTP/1.1 501 Not Implemented";
   run_test(request, expected_response);
 }
 
@@ -225,3 +258,1035 @@ fn test_missing_method() {
   let expected_response = b"HTTP/1.1 400 Bad Request";
   run_test(request, expected_response);
 }
+
+fn demo_handle_headers(_request: &Request) -> Response {
+  Response::builder()
+    .status(StatusCode::Found)
+    .content_type("text/plain")
+    .header("Location", "/test")
+    .header("Set-Cookie", "a=1")
+    .header("Set-Cookie", "b=2")
+    .body("moved")
+}
+
+#[test]
+fn test_custom_headers() {
+  setup_test_server(create_test_server);
+  let request = b"GET /headers HTTP/1.1\r\n\r\n";
+  let expected = b"HTTP/1.1 302 Found";
+  let response = run_test(request, expected);
+  assert!(response.contains("Location: /test\r\n"));
+  assert!(response.contains("Set-Cookie: a=1\r\nSet-Cookie: b=2\r\n"));
+  assert!(response.contains("Content-Length: 5\r\n"));
+}
+
+const BINARY_PAYLOAD: &[u8] = b"%PDF-1.4\n\xff\xfe\x00\x80\xc3\x28\r\n\xe2\x82";
+
+fn demo_handle_binary(_request: &Request) -> Response {
+  Response::builder()
+    .content_type("application/pdf")
+    .body(BINARY_PAYLOAD)
+}
+
+#[test]
+fn test_binary_response_is_verbatim() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"GET /binary HTTP/1.1\r\n\r\n");
+  let (head, body) = split_response(&raw);
+  assert!(head.contains(&format!("Content-Length: {}", BINARY_PAYLOAD.len())));
+  assert_eq!(body, BINARY_PAYLOAD);
+}
+
+#[test]
+fn test_binary_file_is_verbatim() {
+  setup_test_server(create_test_server);
+  let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("res/binary.bin");
+  let fixture = std::fs::read(fixture_path).unwrap();
+  let raw = send_raw(b"GET /binary.bin HTTP/1.1\r\n\r\n");
+  let (head, body) = split_response(&raw);
+  assert!(head.contains("Content-Type: application/octet-stream"));
+  assert!(head.contains(&format!("Content-Length: {}", fixture.len())));
+  assert_eq!(body, fixture);
+}
+
+fn demo_handle_echo(request: &Request) -> Response {
+  Response::builder()
+    .content_type("application/octet-stream")
+    .body(request.body.clone())
+}
+
+#[test]
+fn test_binary_request_body() {
+  setup_test_server(create_test_server);
+  let mut request = format!("POST /echo HTTP/1.1\r\nContent-Length: {}\r\n\r\n", BINARY_PAYLOAD.len()).into_bytes();
+  request.extend_from_slice(BINARY_PAYLOAD);
+  let raw = send_raw(&request);
+  let (_, body) = split_response(&raw);
+  assert_eq!(body, BINARY_PAYLOAD);
+}
+
+fn demo_handle_overlap_param(request: &Request) -> Response {
+  let name = request.params.get("name").cloned().unwrap_or_default();
+  Response::builder().body(format!("param {}", name))
+}
+
+fn demo_handle_overlap_static(_request: &Request) -> Response {
+  Response::builder().body("static")
+}
+
+#[test]
+fn test_static_route_wins_over_param() {
+  setup_test_server(create_test_server);
+  for _ in 0..5 {
+    run_test(b"GET /overlap/static HTTP/1.1\r\n\r\n", b"static");
+    run_test(b"GET /overlap/dynamic HTTP/1.1\r\n\r\n", b"param dynamic");
+  }
+}
+
+#[test]
+#[should_panic(expected = "ambiguous route")]
+fn test_ambiguous_route_panics() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.add_route("/users/{id}", Rt::GET, handler!(demo_handle_get));
+  server.add_route("/users/{name}", Rt::GET, handler!(demo_handle_get));
+}
+
+fn demo_handle_catch_all(request: &Request) -> Response {
+  let mut ordered: BTreeMap<&String, &String> = BTreeMap::new();
+  for (k, v) in &request.params {
+    ordered.insert(k, v);
+  }
+  Response::builder().body(format!("Params: {:?}", ordered))
+}
+
+#[test]
+fn test_catch_all_route() {
+  setup_test_server(create_test_server);
+  run_test(
+    b"GET /files/docs/2024/report.pdf HTTP/1.1\r\n\r\n",
+    b"Params: {\"rest\": \"docs/2024/report.pdf\"}",
+  );
+  run_test(b"GET /api/v1/users HTTP/1.1\r\n\r\n", b"Params: {\"*\": \"v1/users\"}");
+  run_test(b"GET /files HTTP/1.1\r\n\r\n", b"HTTP/1.1 404 Not Found");
+}
+
+fn demo_handle_item_id(request: &Request) -> Response {
+  let id: u64 = request.param("id").unwrap();
+  Response::builder().body(format!("id {}", id + 1))
+}
+
+fn demo_handle_item_slug(request: &Request) -> Response {
+  Response::builder().body(format!("slug {}", request.params["slug"]))
+}
+
+fn demo_handle_item_uuid(request: &Request) -> Response {
+  Response::builder().body(format!("uuid {}", request.params["uuid"]))
+}
+
+fn demo_handle_typed(request: &Request) -> Response {
+  match request.param::<u64>("value") {
+    Ok(value) => Response::builder().body(format!("value {}", value)),
+    Err(err) => Response::builder().status(StatusCode::BadRequest).body(err.to_string()),
+  }
+}
+
+#[test]
+fn test_constrained_params() {
+  setup_test_server(create_test_server);
+  run_test(b"GET /items/41 HTTP/1.1\r\n\r\n", b"id 42");
+  run_test(b"GET /items/hello-world HTTP/1.1\r\n\r\n", b"slug hello-world");
+  run_test(
+    b"GET /items/67e55044-10b1-426f-9247-bb680e5fe0c8 HTTP/1.1\r\n\r\n",
+    b"uuid 67e55044-10b1-426f-9247-bb680e5fe0c8",
+  );
+  run_test(b"GET /items/Not_Valid HTTP/1.1\r\n\r\n", b"HTTP/1.1 404 Not Found");
+}
+
+#[test]
+fn test_typed_param_error() {
+  setup_test_server(create_test_server);
+  run_test(b"GET /typed/7 HTTP/1.1\r\n\r\n", b"value 7");
+  run_test(
+    b"GET /typed/seven HTTP/1.1\r\n\r\n",
+    b"invalid value `seven` for parameter `value`",
+  );
+}
+
+#[test]
+fn test_method_not_allowed_lists_allow() {
+  setup_test_server(create_test_server);
+  let response = run_test(b"DELETE /headers HTTP/1.1\r\n\r\n", b"HTTP/1.1 405 Method Not Allowed");
+  assert!(response.contains("Allow: GET, HEAD, OPTIONS\r\n"));
+  let response = run_test(b"GET /test/hola HTTP/1.1\r\n\r\n", b"HTTP/1.1 405 Method Not Allowed");
+  assert!(response.contains("Allow: POST, OPTIONS\r\n"));
+}
+
+fn demo_handle_patch(request: &Request) -> Response {
+  Response::builder().body(format!("patch {}", request.body_str()))
+}
+
+#[test]
+fn test_patch() {
+  setup_test_server(create_test_server);
+  run_test(b"PATCH /test HTTP/1.1\r\nContent-Length: 5\r\n\r\nfield", b"patch field");
+}
+
+#[test]
+fn test_head_derived_from_get() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"HEAD /test HTTP/1.1\r\n\r\n");
+  let (head, body) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 200 OK"));
+  assert!(head.contains("Content-Length: 3"));
+  assert!(body.is_empty());
+
+  let raw = send_raw(b"HEAD /binary.bin HTTP/1.1\r\n\r\n");
+  let (head, body) = split_response(&raw);
+  assert!(head.contains("Content-Length: 1020"));
+  assert!(body.is_empty());
+}
+
+#[test]
+fn test_automatic_options() {
+  setup_test_server(create_test_server);
+  let response = run_test(b"OPTIONS /test HTTP/1.1\r\n\r\n", b"HTTP/1.1 204 No Content");
+  assert!(response.contains("Allow: GET, POST, PUT, DELETE, HEAD, OPTIONS, PATCH\r\n"));
+  assert!(!response.contains("Content-Length"));
+}
+
+fn demo_handle_propfind(request: &Request) -> Response {
+  Response::builder().body(format!("{} handled", request.method))
+}
+
+#[test]
+fn test_extension_method() {
+  setup_test_server(create_test_server);
+  run_test(b"PROPFIND /dav HTTP/1.1\r\n\r\n", b"PROPFIND handled");
+  let response = run_test(b"PROPFIND /test HTTP/1.1\r\n\r\n", b"HTTP/1.1 405 Method Not Allowed");
+  assert!(response.contains("Allow: GET, POST, PUT, DELETE, HEAD, OPTIONS, PATCH\r\n"));
+}
+
+#[test]
+fn test_unknown_method_is_not_coerced() {
+  setup_test_server(create_test_server);
+  let response = run_test(b"DELETEX /test HTTP/1.1\r\n\r\n", b"HTTP/1.1 501 Not Implemented");
+  assert!(!response.contains("get"));
+  run_test(b"GE(T /test HTTP/1.1\r\n\r\n", b"HTTP/1.1 400 Bad Request");
+}
+
+fn demo_handle_raw(request: &Request) -> Response {
+  Response::builder().body(format!("{} | {} | {}", request.path, request.params["name"], request.raw_target))
+}
+
+#[test]
+fn test_percent_decoding() {
+  setup_test_server(create_test_server);
+  run_test(
+    b"POST /test/hola%20mundo?q=hello%20world&name=a+b HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+    b"Params: {\"name\": \"a b\", \"param1\": \"hola mundo\", \"q\": \"hello world\"}",
+  );
+  run_test(
+    b"GET /raw/a%2Fb?x=%41 HTTP/1.1\r\n\r\n",
+    b"/raw/a/b | a/b | /raw/a%2Fb?x=%41",
+  );
+  run_test(b"GET /files/my%20doc.pdf HTTP/1.1\r\n\r\n", b"Params: {\"rest\": \"my doc.pdf\"}");
+  run_test(b"GET /hello%20world.txt HTTP/1.1\r\n\r\n", b"hello from a spaced name");
+}
+
+fn demo_handle_split(request: &Request) -> Response {
+  Response::builder().body(format!(
+    "path id: {} | query id: {:?} | tags: {:?} | params id: {} | path params: {}",
+    request.path_params()["id"],
+    request.query_param("id"),
+    request.query_all("tag"),
+    request.params["id"],
+    request.path_params().len()
+  ))
+}
+
+#[test]
+fn test_path_and_query_params_are_separate() {
+  setup_test_server(create_test_server);
+  run_test(
+    b"GET /split/7?id=evil&tag=a&tag=b HTTP/1.1\r\n\r\n",
+    b"path id: 7 | query id: Some(\"evil\") | tags: [\"a\", \"b\"] | params id: 7 | path params: 1",
+  );
+}
+
+fn demo_handle_inspect(request: &Request) -> Response {
+  let headers = &request.headers;
+  Response::builder().body(format!(
+    "host={} type={} length={:?} tags={}",
+    headers.host().unwrap_or("-"),
+    headers.content_type().unwrap_or("-"),
+    headers.content_length(),
+    headers.get_all("x-tag").join("|"),
+  ))
+}
+
+#[test]
+fn test_request_headers() {
+  setup_test_server(create_test_server);
+  run_test(
+    b"GET /inspect HTTP/1.1\r\nHost:example.com\r\ncontent-type:  text/plain; q=a: b \r\nContent-Length: 0\r\nX-Tag: one\r\nx-tag:\ttwo\r\n\r\n",
+    b"host=example.com type=text/plain; q=a: b length=Some(0) tags=one|two",
+  );
+}
+
+#[test]
+fn test_malformed_request_header() {
+  setup_test_server(create_test_server);
+  run_test(b"GET /inspect HTTP/1.1\r\nHost example.com\r\n\r\n", b"HTTP/1.1 400 Bad Request");
+  run_test(b"GET /inspect HTTP/1.1\r\nHost : example.com\r\n\r\n", b"HTTP/1.1 400 Bad Request");
+}
+
+#[test]
+fn test_keep_alive_pipelining() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_auto_close(false);
+  server.set_max_requests(3);
+  server.set_keep_alive_timeout(std::time::Duration::from_millis(300));
+  server.add_route("/test", Rt::GET, handler!(demo_handle_get));
+  server.add_route("/test", Rt::POST, handler!(demo_handle_post));
+  let addr = spawn_server(server);
+
+  // Three pipelined requests, the last one asking to close.
+  let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+  stream
+    .write_all(
+      b"GET /test HTTP/1.1\r\n\r\n\
+        POST /test HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody\
+        GET /test HTTP/1.1\r\nConnection: close\r\n\r\n",
+    )
+    .unwrap();
+  let mut answer = String::new();
+  stream.read_to_string(&mut answer).unwrap();
+  assert_eq!(answer.matches("HTTP/1.1 200 OK").count(), 3);
+  assert!(answer.contains("Body: \"body\""));
+  assert_eq!(answer.matches("Connection: close").count(), 1);
+
+  // An idle connection is closed once the keep-alive timeout expires.
+  let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+  stream.write_all(b"GET /test HTTP/1.1\r\n\r\n").unwrap();
+  let mut answer = String::new();
+  stream.read_to_string(&mut answer).unwrap();
+  assert_eq!(answer.matches("HTTP/1.1 200 OK").count(), 1);
+  assert!(!answer.contains("Connection: close"));
+
+  // No more than the request limit is served on one connection.
+  let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+  stream.write_all(&b"GET /test HTTP/1.1\r\n\r\n".repeat(4)).unwrap();
+  let mut answer = String::new();
+  stream.read_to_string(&mut answer).unwrap();
+  assert_eq!(answer.matches("HTTP/1.1 200 OK").count(), 3);
+  assert_eq!(answer.matches("Connection: close").count(), 1);
+}
+
+#[test]
+fn test_chunked_request_body() {
+  setup_test_server(create_test_server);
+  run_test(
+    b"POST /test HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nmueve \r\n3;ext=1\r\ntu \r\n6\r\ncuerpo\r\n0\r\n\r\n",
+    b"Body: \"mueve tu cuerpo\"",
+  );
+  run_test(
+    b"GET /inspect HTTP/1.1\r\nHost: example.com\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nX-Tag: late\r\n\r\n",
+    b"tags=late",
+  );
+}
+
+#[test]
+fn test_bad_transfer_encoding() {
+  setup_test_server(create_test_server);
+  run_test(
+    b"POST /test HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nmueve\r\n0\r\n\r\n",
+    b"HTTP/1.1 400 Bad Request",
+  );
+  run_test(
+    b"POST /test HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nmueve\r\n0\r\n\r\n",
+    b"HTTP/1.1 400 Bad Request",
+  );
+  run_test(
+    b"POST /test HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\nmueve",
+    b"HTTP/1.1 400 Bad Request",
+  );
+  run_test(
+    b"POST /test HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n",
+    b"HTTP/1.1 501 Not Implemented",
+  );
+  run_test(
+    b"POST /test HTTP/1.1\r\nContent-Length: 4\r\nContent-Length: 5\r\n\r\nmueve",
+    b"HTTP/1.1 400 Bad Request",
+  );
+}
+
+#[test]
+fn test_length_required_on_kept_alive_connection() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_auto_close(false);
+  server.add_route("/test", Rt::POST, handler!(demo_handle_post));
+  let addr = spawn_server(server);
+
+  let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+  stream
+    .write_all(
+      b"POST /test HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nbody\r\n0\r\n\r\n\
+        POST /test HTTP/1.1\r\n\r\nbody",
+    )
+    .unwrap();
+  let mut answer = String::new();
+  stream.read_to_string(&mut answer).unwrap();
+  assert!(answer.starts_with("HTTP/1.1 200 OK"));
+  assert!(answer.contains("Body: \"body\""));
+  assert!(answer.contains("HTTP/1.1 411 Length Required"));
+  assert!(answer.ends_with("Connection: close\r\n\r\n"));
+}
+
+fn demo_handle_stream(_request: &Request) -> Response {
+  let rows = (1..=3).map(|i| format!("row {}\n", i).into_bytes());
+  Response::builder().content_type("text/csv").body(Body::chunks(rows))
+}
+
+#[test]
+fn test_streamed_response_is_chunked() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"GET /stream HTTP/1.1\r\n\r\n");
+  let (head, body) = split_response(&raw);
+  assert!(head.contains("Transfer-Encoding: chunked"));
+  assert!(!head.contains("Content-Length"));
+  assert_eq!(body, b"6\r\nrow 1\n\r\n6\r\nrow 2\n\r\n6\r\nrow 3\n\r\n0\r\n\r\n");
+
+  let raw = send_raw(b"HEAD /stream HTTP/1.1\r\n\r\n");
+  let (head, body) = split_response(&raw);
+  assert!(head.contains("Transfer-Encoding: chunked"));
+  assert!(body.is_empty());
+}
+
+#[test]
+fn test_file_response_is_streamed() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"GET /binary.bin HTTP/1.1\r\n\r\n");
+  let (head, body) = split_response(&raw);
+  assert!(head.contains("Content-Length: 1020"));
+  assert_eq!(body.len(), 1020);
+}
+
+#[test]
+fn test_request_size_limits() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_limits(Limits {
+    max_header_bytes: 256,
+    max_headers: 4,
+    max_body_bytes: 16,
+  });
+  server.add_route("/test", Rt::POST, handler!(demo_handle_post));
+  server.add_route("/upload", Rt::POST, handler!(demo_handle_post));
+  server.set_body_limit("/upload", Rt::POST, 64);
+  let addr = spawn_server(server);
+
+  let too_many = b"POST /test HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n";
+  assert!(send_text_to(&addr, too_many).starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+  let long_header = format!("POST /test HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(300));
+  assert!(send_text_to(&addr, long_header.as_bytes()).starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+
+  let huge_length = b"POST /test HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n";
+  assert!(send_text_to(&addr, huge_length).starts_with("HTTP/1.1 413 Payload Too Large"));
+  let long_chunks = b"POST /test HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n8\r\n12345678\r\n9\r\n123456789\r\n0\r\n\r\n";
+  assert!(send_text_to(&addr, long_chunks).starts_with("HTTP/1.1 413 Payload Too Large"));
+  // A body sent without framing can't be told apart from the next request.
+  let unframed = format!("POST /test HTTP/1.1\r\n\r\n{}", "b".repeat(17));
+  assert!(send_text_to(&addr, unframed.as_bytes()).starts_with("HTTP/1.1 411 Length Required"));
+
+  // The route limit overrides the server-wide one.
+  let upload = format!("POST /upload HTTP/1.1\r\nContent-Length: 40\r\n\r\n{}", "c".repeat(40));
+  assert!(send_text_to(&addr, upload.as_bytes()).starts_with("HTTP/1.1 200 OK"));
+  let upload = format!("POST /upload HTTP/1.1\r\nContent-Length: 65\r\n\r\n{}", "c".repeat(65));
+  assert!(send_text_to(&addr, upload.as_bytes()).starts_with("HTTP/1.1 413 Payload Too Large"));
+}
+
+fn demo_handle_slow(_request: &Request) -> Response {
+  std::thread::sleep(std::time::Duration::from_millis(1000));
+  Response {
+    status: StatusCode::Ok.to_string(),
+    content_type: "text/plain".to_string(),
+    content: "slow".into(),
+    headers: Headers::new(),
+  }
+}
+
+#[test]
+fn test_connection_timeouts() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_timeouts(Timeouts {
+    header_read: Some(std::time::Duration::from_millis(300)),
+    body_read: Some(std::time::Duration::from_millis(300)),
+    handler: Some(std::time::Duration::from_millis(300)),
+    write: Some(std::time::Duration::from_secs(1)),
+  });
+  server.add_route("/test", Rt::POST, handler!(demo_handle_post));
+  server.add_route("/slow", Rt::GET, handler!(demo_handle_slow));
+  let addr = spawn_server(server);
+
+  // A slow client sends its request in pieces, pausing longer than allowed.
+  let pause = std::time::Duration::from_millis(200);
+
+  let head = send_pieces_text_to(&addr, &[b"GET /test HTTP/1.1\r\n", b"Host: a\r\n", b"X-A: 1\r\n"], pause);
+  assert!(head.starts_with("HTTP/1.1 408 Request Timeout"), "{}", head);
+  let body = send_pieces_text_to(&addr, &[b"POST /test HTTP/1.1\r\nContent-Length: 6\r\n\r\nab", b"cd"], std::time::Duration::from_millis(400));
+  assert!(body.starts_with("HTTP/1.1 408 Request Timeout"), "{}", body);
+
+  // Pieces arriving within the deadline are fine.
+  let quick = send_pieces_text_to(&addr, &[b"POST /test HTTP/1.1\r\nConnection: close\r\n", b"Content-Length: 2\r\n\r\nok"], std::time::Duration::from_millis(50));
+  assert!(quick.starts_with("HTTP/1.1 200 OK"), "{}", quick);
+
+  let slow = send_pieces_text_to(&addr, &[b"GET /slow HTTP/1.1\r\n\r\n"], std::time::Duration::ZERO);
+  assert!(slow.starts_with("HTTP/1.1 503 Service Unavailable"), "{}", slow);
+}
+
+#[test]
+fn test_stop_finishes_in_flight_requests() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_auto_close(false);
+  server.add_route("/slow", Rt::GET, handler!(demo_handle_slow));
+  let addr = server.local_addr().unwrap().to_string();
+  let server = Arc::new(server);
+  let running = Arc::clone(&server);
+  let server_thread = std::thread::spawn(move || running.run());
+
+  // The request in flight when the server is stopped is still answered, and
+  // its connection closed afterwards.
+  let mut in_flight = std::net::TcpStream::connect(&addr).unwrap();
+  in_flight.write_all(b"GET /slow HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+  std::thread::sleep(Duration::from_millis(200));
+  server.stop();
+  let mut answer = String::new();
+  in_flight.read_to_string(&mut answer).unwrap();
+  assert!(answer.starts_with("HTTP/1.1 200 OK"), "{}", answer);
+  assert!(answer.contains("Connection: close\r\n"), "{}", answer);
+  assert!(answer.ends_with("slow"), "{}", answer);
+
+  // `run` returns once the workers are done.
+  server_thread.join().unwrap();
+}
+
+#[test]
+fn test_shutdown_handle_closes_idle_connections() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_auto_close(false);
+  server.set_keep_alive_timeout(Duration::from_secs(30));
+  server.add_route("/test", Rt::GET, handler!(demo_handle_get));
+  let addr = server.local_addr().unwrap().to_string();
+  let handle = server.shutdown_handle();
+  let server_thread = std::thread::spawn(move || server.run());
+
+  let mut idle = std::net::TcpStream::connect(&addr).unwrap();
+  idle.write_all(b"GET /test HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+  let mut first = [0u8; 15];
+  idle.read_exact(&mut first).unwrap();
+  assert_eq!(&first, b"HTTP/1.1 200 OK");
+
+  // The idle kept-alive connection doesn't hold the shutdown back until the
+  // keep-alive timeout; it is closed right away.
+  let started = Instant::now();
+  handle.shutdown();
+  server_thread.join().unwrap();
+  assert!(started.elapsed() < Duration::from_secs(5));
+  let mut rest = Vec::new();
+  idle.read_to_end(&mut rest).unwrap();
+  assert!(!String::from_utf8_lossy(&rest).contains("HTTP/1.1"));
+}
+
+#[test]
+fn test_server_builder() {
+  let server = ServerBuilder::new("127.0.0.1:0")
+    .workers(2)
+    .auto_close(false)
+    .limits(Limits {
+      max_body_bytes: 8,
+      ..Limits::default()
+    })
+    .route("/test", Rt::POST, handler!(demo_handle_post))
+    .route("/upload", Rt::POST, handler!(demo_handle_post))
+    .body_limit("/upload", Rt::POST, 64)
+    .build()
+    .unwrap();
+  let addr = spawn_server(server);
+
+  // Kept alive, as configured: both pipelined requests are answered on one
+  // connection, the first within its route's own body limit.
+  let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+  let body = "x".repeat(20);
+  let requests = format!(
+    "POST /upload HTTP/1.1\r\nContent-Length: 20\r\n\r\n{}POST /test HTTP/1.1\r\nContent-Length: 20\r\n\r\n{}",
+    body, body
+  );
+  stream.write_all(requests.as_bytes()).unwrap();
+  let mut answer = String::new();
+  stream.read_to_string(&mut answer).unwrap();
+  assert!(answer.starts_with("HTTP/1.1 200 OK"), "{}", answer);
+  assert!(answer.contains("HTTP/1.1 413 Payload Too Large"), "{}", answer);
+}
+
+#[test]
+fn test_middleware() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.add_route("/test", Rt::GET, handler!(demo_handle_get));
+  server.add_route("/admin/test", Rt::GET, handler!(demo_handle_get));
+  server.add_route("/echo", Rt::POST, handler!(demo_handle_post));
+  let trace = |name: &'static str| {
+    after(move |_req, mut resp| {
+      resp.headers.append("X-Trace", name);
+      resp
+    })
+  };
+  server.add_middleware(trace("global"));
+  server.add_group_middleware("/admin", trace("group"));
+  server.add_group_middleware(
+    "/admin",
+    before(|req| match req.headers.get("X-Token") {
+      Some("secret") => None,
+      _ => Some(Response::builder().status(StatusCode::Unauthorized).body("denied")),
+    }),
+  );
+  server.add_route_middleware("/admin/test", Rt::GET, trace("route"));
+  server.add_route_middleware(
+    "/echo",
+    Rt::POST,
+    around(|req, next| {
+      Box::pin(async move {
+        req.body = b"rewritten".to_vec();
+        next.run(req).await
+      })
+    }),
+  );
+  let addr = spawn_server(server);
+
+  // Responses pass back out through the middlewares innermost first.
+  let admin = send_text_to(&addr, b"GET /admin/test HTTP/1.1\r\nX-Token: secret\r\n\r\n");
+  assert!(admin.starts_with("HTTP/1.1 200 OK"), "{}", admin);
+  assert!(admin.contains("X-Trace: route\r\nX-Trace: group\r\nX-Trace: global\r\n"), "{}", admin);
+
+  // A group middleware answering itself skips the handler and the route's
+  // own middlewares.
+  let denied = send_text_to(&addr, b"GET /admin/test HTTP/1.1\r\n\r\n");
+  assert!(denied.starts_with("HTTP/1.1 401 Unauthorized"), "{}", denied);
+  assert!(denied.contains("X-Trace: group\r\nX-Trace: global\r\n"), "{}", denied);
+  assert!(!denied.contains("X-Trace: route"), "{}", denied);
+
+  // Only the server-wide middlewares run outside the group, unrouted
+  // requests included.
+  let plain = send_text_to(&addr, b"GET /test HTTP/1.1\r\n\r\n");
+  assert!(plain.contains("X-Trace: global\r\n") && !plain.contains("X-Trace: group"), "{}", plain);
+  let missing = send_text_to(&addr, b"GET /nowhere HTTP/1.1\r\n\r\n");
+  assert!(missing.starts_with("HTTP/1.1 404 Not Found"), "{}", missing);
+  assert!(missing.contains("X-Trace: global\r\n"), "{}", missing);
+
+  // The handler sees the request as the middleware left it.
+  let echo = send_text_to(&addr, b"POST /echo HTTP/1.1\r\nContent-Length: 8\r\n\r\noriginal");
+  assert!(echo.contains("Body: \"rewritten\""), "{}", echo);
+}
+
+#[test]
+fn test_nested_routers() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  let tag = |name: &'static str| {
+    after(move |_req, mut resp| {
+      resp.headers.append("X-Router", name);
+      resp
+    })
+  };
+  let users = Router::new()
+    .route("/users", Rt::GET, handler!(demo_handle_get))
+    .route("/users/{id}", Rt::POST, handler!(demo_handle_post))
+    .middleware(tag("users"));
+  let api = Router::new().nest("/v1", users).middleware(tag("api"));
+  server.nest("/api", api);
+  server.merge(Router::new().route("/", Rt::GET, handler!(demo_handle_home)));
+  let addr = spawn_server(server);
+
+  // Nested routes answer under the joined prefix, through the middlewares of
+  // every router they belong to, outermost first.
+  let list = send_text_to(&addr, b"GET /api/v1/users HTTP/1.1\r\n\r\n");
+  assert!(list.starts_with("HTTP/1.1 200 OK"), "{}", list);
+  assert!(list.contains("X-Router: users\r\nX-Router: api\r\n"), "{}", list);
+  let user = send_text_to(&addr, b"POST /api/v1/users/7 HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+  assert!(user.contains("Uri: /api/v1/users/7"), "{}", user);
+  assert!(user.contains("\"id\": \"7\""), "{}", user);
+
+  // Nothing is registered without the prefix, and router middlewares stay
+  // out of other routes.
+  let bare = send_text_to(&addr, b"GET /users HTTP/1.1\r\n\r\n");
+  assert!(bare.starts_with("HTTP/1.1 404 Not Found") && !bare.contains("X-Router"), "{}", bare);
+  let home = send_text_to(&addr, b"GET / HTTP/1.1\r\n\r\n");
+  assert!(home.starts_with("HTTP/1.1 200 OK") && !home.contains("X-Router"), "{}", home);
+}
+
+fn demo_handle_fallback(request: &Request) -> Response {
+  Response::builder()
+    .status(StatusCode::NotFound)
+    .content_type("text/plain")
+    .body(format!("nothing at {}", request.path))
+}
+
+#[test]
+fn test_fallback_and_error_pages() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.add_route("/test", Rt::GET, handler!(demo_handle_get));
+  server.set_fallback(handler!(demo_handle_fallback));
+  server.set_error_page(StatusCode::MethodNotAllowed, |request, status| {
+    Response::builder()
+      .content_type("application/problem+json")
+      .body(format!("{{\"status\":{},\"instance\":\"{}\"}}", status.code(), request.path))
+  });
+  server.set_default_error_page(|_request, status| {
+    Response::builder()
+      .content_type("text/html")
+      .body(format!("<h1>{}</h1>", status))
+  });
+  let addr = spawn_server(server);
+
+  let missing = send_text_to(&addr, b"GET /nowhere HTTP/1.1\r\n\r\n");
+  assert!(missing.starts_with("HTTP/1.1 404 Not Found"), "{}", missing);
+  assert!(missing.ends_with("nothing at /nowhere"), "{}", missing);
+
+  // Router misses are rendered, keeping the headers the server set.
+  let wrong_method = send_text_to(&addr, b"DELETE /test HTTP/1.1\r\n\r\n");
+  assert!(wrong_method.starts_with("HTTP/1.1 405 Method Not Allowed"), "{}", wrong_method);
+  assert!(wrong_method.contains("Content-Type: application/problem+json\r\n"), "{}", wrong_method);
+  assert!(wrong_method.contains("Allow: "), "{}", wrong_method);
+  assert!(wrong_method.ends_with("{\"status\":405,\"instance\":\"/test\"}"), "{}", wrong_method);
+
+  // So are protocol errors, before any routing.
+  let malformed = send_text_to(&addr, b"G(T /test HTTP/1.1\r\n\r\n");
+  assert!(malformed.starts_with("HTTP/1.1 400 Bad Request"), "{}", malformed);
+  assert!(malformed.ends_with("<h1>400 Bad Request</h1>"), "{}", malformed);
+  let old = send_text_to(&addr, b"GET /test HTTP/1.0\r\n\r\n");
+  assert!(old.starts_with("HTTP/1.1 505 HTTP Version Not Supported"), "{}", old);
+  assert!(old.ends_with("<h1>505 HTTP Version Not Supported</h1>"), "{}", old);
+
+  // Answers from handlers are left alone.
+  let found = send_text_to(&addr, b"GET /test HTTP/1.1\r\n\r\n");
+  assert!(found.starts_with("HTTP/1.1 200 OK") && !found.contains("<h1>"), "{}", found);
+}
+
+fn demo_return_text(_request: &Request) -> String {
+  "plain text".to_string()
+}
+
+fn demo_return_created(_request: &Request) -> (StatusCode, &'static str) {
+  (StatusCode::Created, "made")
+}
+
+fn demo_return_lookup(request: &Request) -> Option<String> {
+  request.query_param("name").map(|name| format!("hi {}", name))
+}
+
+fn demo_return_checked(request: &Request) -> Result<Vec<u8>, (StatusCode, String)> {
+  if request.body.is_empty() {
+    return Err((StatusCode::UnprocessableEntity, "empty body".to_string()));
+  }
+  Ok(request.body.clone())
+}
+
+#[cfg(feature = "json")]
+fn demo_return_json(request: &Request) -> httpageboy::Json<BTreeMap<&'static str, String>> {
+  httpageboy::Json(BTreeMap::from([("path", request.path.clone())]))
+}
+
+#[test]
+fn test_handler_return_types() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.add_route("/text", Rt::GET, handler!(demo_return_text));
+  server.add_route("/created", Rt::POST, handler!(demo_return_created));
+  server.add_route("/lookup", Rt::GET, handler!(demo_return_lookup));
+  server.add_route("/checked", Rt::POST, handler!(demo_return_checked));
+  #[cfg(feature = "json")]
+  server.add_route("/json", Rt::GET, handler!(demo_return_json));
+  let addr = spawn_server(server);
+
+  let text = send_text_to(&addr, b"GET /text HTTP/1.1\r\n\r\n");
+  assert!(text.starts_with("HTTP/1.1 200 OK") && text.contains("Content-Type: text/plain\r\n"), "{}", text);
+  assert!(text.ends_with("\r\n\r\nplain text"), "{}", text);
+  let created = send_text_to(&addr, b"POST /created HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+  assert!(created.starts_with("HTTP/1.1 201 Created") && created.ends_with("made"), "{}", created);
+
+  let found = send_text_to(&addr, b"GET /lookup?name=ana HTTP/1.1\r\n\r\n");
+  assert!(found.starts_with("HTTP/1.1 200 OK") && found.ends_with("hi ana"), "{}", found);
+  let missing = send_text_to(&addr, b"GET /lookup HTTP/1.1\r\n\r\n");
+  assert!(missing.starts_with("HTTP/1.1 404 Not Found"), "{}", missing);
+
+  let ok = send_text_to(&addr, b"POST /checked HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc");
+  assert!(ok.starts_with("HTTP/1.1 200 OK") && ok.contains("Content-Type: application/octet-stream\r\n"), "{}", ok);
+  assert!(ok.ends_with("abc"), "{}", ok);
+  let err = send_text_to(&addr, b"POST /checked HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+  assert!(err.starts_with("HTTP/1.1 422 Unprocessable Entity") && err.ends_with("empty body"), "{}", err);
+
+  #[cfg(feature = "json")]
+  {
+    let json = send_text_to(&addr, b"GET /json HTTP/1.1\r\n\r\n");
+    assert!(json.contains("Content-Type: application/json\r\n"), "{}", json);
+    assert!(json.ends_with("{\"path\":\"/json\"}"), "{}", json);
+  }
+}
+
+#[test]
+fn test_into_response_conversions() {
+  let response = (StatusCode::Accepted, "queued").into_response();
+  assert_eq!(response.status, "202 Accepted");
+  assert_eq!(response.content.as_bytes(), Some(&b"queued"[..]));
+  let response = StatusCode::NoContent.into_response();
+  assert_eq!(response.status, "204 No Content");
+  assert!(response.content.is_empty());
+  let response: Result<String, StatusCode> = Err(StatusCode::Forbidden);
+  assert_eq!(response.into_response().status, "403 Forbidden");
+}
+
+#[cfg(feature = "json")]
+#[derive(serde::Deserialize)]
+struct DemoPage {
+  page: u32,
+  #[serde(default)]
+  tag: Vec<String>,
+  sort: Option<String>,
+}
+
+#[cfg(feature = "json")]
+#[derive(serde::Deserialize)]
+struct DemoUser {
+  name: String,
+  age: u8,
+}
+
+#[cfg(feature = "json")]
+fn demo_extract_user(Path(id): Path<u64>, Query(page): Query<DemoPage>, Header(agent): Header<UserAgent>) -> String {
+  format!("user {} page {} tags {} sort {:?} agent {}", id, page.page, page.tag.join(","), page.sort, agent.0)
+}
+
+#[cfg(feature = "json")]
+fn demo_extract_json(Json(user): Json<DemoUser>) -> String {
+  format!("json {} {}", user.name, user.age)
+}
+
+#[cfg(feature = "json")]
+fn demo_extract_form(Form(user): Form<DemoUser>) -> String {
+  format!("form {} {}", user.name, user.age)
+}
+
+fn demo_extract_state(State(greeting): State<String>, body: String) -> String {
+  format!("{} {}", greeting, body)
+}
+
+#[test]
+fn test_handler_extractors() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.add_state("hello".to_string());
+  server.add_route("/state", Rt::POST, handler!(demo_extract_state));
+  #[cfg(feature = "json")]
+  {
+    server.add_route("/users/{id}", Rt::GET, handler!(demo_extract_user));
+    server.add_route("/json", Rt::POST, handler!(demo_extract_json));
+    server.add_route("/form", Rt::POST, handler!(demo_extract_form));
+  }
+  let addr = spawn_server(server);
+
+  let state = send_text_to(&addr, b"POST /state HTTP/1.1\r\nContent-Length: 5\r\n\r\nworld");
+  assert!(state.starts_with("HTTP/1.1 200 OK") && state.ends_with("hello world"), "{}", state);
+  let not_text = send_text_to(&addr, b"POST /state HTTP/1.1\r\nContent-Length: 1\r\n\r\n\xff");
+  assert!(not_text.starts_with("HTTP/1.1 400 Bad Request"), "{}", not_text);
+
+  #[cfg(feature = "json")]
+  {
+    let user = send_text_to(&addr, b"GET /users/42?page=2&tag=a&tag=b HTTP/1.1\r\nUser-Agent: probe\r\n\r\n");
+    assert!(user.starts_with("HTTP/1.1 200 OK"), "{}", user);
+    assert!(user.ends_with("user 42 page 2 tags a,b sort None agent probe"), "{}", user);
+    let bad_id = send_text_to(&addr, b"GET /users/abc?page=2 HTTP/1.1\r\nUser-Agent: probe\r\n\r\n");
+    assert!(bad_id.starts_with("HTTP/1.1 400 Bad Request") && bad_id.contains("`abc`"), "{}", bad_id);
+    let bad_query = send_text_to(&addr, b"GET /users/42?sort=up HTTP/1.1\r\nUser-Agent: probe\r\n\r\n");
+    assert!(bad_query.starts_with("HTTP/1.1 400 Bad Request") && bad_query.contains("page"), "{}", bad_query);
+    let no_agent = send_text_to(&addr, b"GET /users/42?page=1 HTTP/1.1\r\n\r\n");
+    assert!(no_agent.starts_with("HTTP/1.1 400 Bad Request") && no_agent.ends_with("missing header User-Agent"), "{}", no_agent);
+
+    let json = send_text_to(&addr, b"POST /json HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 23\r\n\r\n{\"name\":\"ana\",\"age\":30}");
+    assert!(json.starts_with("HTTP/1.1 200 OK") && json.ends_with("json ana 30"), "{}", json);
+    let wrong_type = send_text_to(&addr, b"POST /json HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\n{}");
+    assert!(wrong_type.starts_with("HTTP/1.1 415 Unsupported Media Type"), "{}", wrong_type);
+    let malformed = send_text_to(&addr, b"POST /json HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 8\r\n\r\n{\"name\":");
+    assert!(malformed.starts_with("HTTP/1.1 400 Bad Request"), "{}", malformed);
+    let unfit = send_text_to(&addr, b"POST /json HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 14\r\n\r\n{\"name\":\"ana\"}");
+    assert!(unfit.starts_with("HTTP/1.1 422 Unprocessable Entity"), "{}", unfit);
+
+    let form = send_text_to(&addr, b"POST /form HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded; charset=utf-8\r\nContent-Length: 21\r\n\r\nname=ana+maria&age=30");
+    assert!(form.starts_with("HTTP/1.1 200 OK") && form.ends_with("form ana maria 30"), "{}", form);
+    let bad_form = send_text_to(&addr, b"POST /form HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 15\r\n\r\nname=ana&age=xx");
+    assert!(bad_form.starts_with("HTTP/1.1 422 Unprocessable Entity"), "{}", bad_form);
+    let form_type = send_text_to(&addr, b"POST /form HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}");
+    assert!(form_type.starts_with("HTTP/1.1 415 Unsupported Media Type"), "{}", form_type);
+  }
+}
+
+#[test]
+fn test_truncated_body_is_rejected() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"POST /echo HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc");
+  let (head, _) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 400 Bad Request"), "{}", head);
+}
+
+#[test]
+fn test_transfer_encoding_with_content_length() {
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_auto_close(false);
+  server.add_route("/test", Rt::GET, handler!(demo_handle_get));
+  server.add_route("/test", Rt::POST, handler!(demo_handle_post));
+  let addr = spawn_server(server);
+
+  // The GET hidden in the chunked body must not be answered as a request.
+  let answer = send_raw_to(
+    &addr,
+    b"POST /test HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 4\r\n\r\n\
+      0\r\n\r\nGET /test HTTP/1.1\r\n\r\n",
+  );
+  let answer = String::from_utf8_lossy(&answer);
+  assert!(answer.starts_with("HTTP/1.1 400 Bad Request"), "{}", answer);
+  assert_eq!(answer.matches("HTTP/1.1").count(), 1, "{}", answer);
+}
+
+#[test]
+fn test_timed_handlers_share_a_bounded_pool() {
+  let mut server = Server::new("127.0.0.1:0", 1, None).unwrap();
+  server.set_timeouts(Timeouts {
+    handler: Some(Duration::from_millis(200)),
+    ..Timeouts::default()
+  });
+  server.add_route("/test", Rt::GET, handler!(demo_handle_get));
+  server.add_route("/slow", Rt::GET, handler!(demo_handle_slow));
+  let addr = spawn_server(server);
+
+  assert!(send_text_to(&addr, b"GET /slow HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 503 Service Unavailable"));
+  // The only handler thread is still busy with the slow handler, so a quick
+  // request waits for it and runs out of time instead of getting a thread.
+  assert!(send_text_to(&addr, b"GET /test HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 503 Service Unavailable"));
+  std::thread::sleep(Duration::from_millis(1000));
+  assert!(send_text_to(&addr, b"GET /test HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200 OK"));
+}
+
+#[test]
+fn test_no_empty_content_type() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"G(T /test HTTP/1.1\r\n\r\n");
+  let (head, _) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 400 Bad Request"), "{}", head);
+  assert!(!head.contains("Content-Type"), "{}", head);
+}
+
+#[test]
+fn test_bodiless_methods_with_open_connection() {
+  setup_test_server(create_test_server);
+  // The client keeps its side open, so a server waiting for a body would
+  // never answer.
+  let wait = std::time::Duration::from_secs(2);
+  let raw = send_keeping_open(server_url(), b"HEAD /test HTTP/1.1\r\nHost: x\r\n\r\n", wait);
+  let (head, body) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+  assert!(body.is_empty());
+  let raw = send_keeping_open(server_url(), b"OPTIONS /test HTTP/1.1\r\nHost: x\r\n\r\n", wait);
+  let (head, _) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 204 No Content"), "{}", head);
+  assert!(head.contains("Allow: GET, POST, PUT, DELETE, HEAD, OPTIONS, PATCH"), "{}", head);
+  let raw = send_keeping_open(server_url(), b"DELETE /test HTTP/1.1\r\nHost: x\r\n\r\n", wait);
+  assert!(String::from_utf8_lossy(&raw).ends_with("delete"));
+}
+
+#[test]
+fn test_obs_text_header_value() {
+  setup_test_server(create_test_server);
+  // A Latin-1 byte in a value neither ends the head nor leaks into the body.
+  let raw = send_raw(b"GET /inspect HTTP/1.1\r\nX-Name: caf\xe9\r\nHost: example.com\r\nX-Tag: a\r\n\r\n");
+  let response = String::from_utf8_lossy(&raw);
+  assert!(response.ends_with("host=example.com type=- length=None tags=a"), "{}", response);
+  let raw = send_raw(b"POST /test HTTP/1.1\r\nX-Name: caf\xe9\r\nContent-Length: 5\r\n\r\nhello");
+  let response = String::from_utf8_lossy(&raw);
+  assert!(response.contains("Body: \"hello\""), "{}", response);
+
+  let raw = send_raw(b"GET /inspect HTTP/1.1\r\nX-Name: a\x00b\r\n\r\n");
+  let (head, _) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 400 Bad Request"), "{}", head);
+}
+
+fn demo_handle_code(request: &Request) -> Response {
+  Response::builder().body(format!("code {}", request.params["code"]))
+}
+
+#[test]
+fn test_constraint_on_long_segment() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"GET /codes/abc0 HTTP/1.1\r\n\r\n");
+  assert!(String::from_utf8_lossy(&raw).ends_with("code abc0"));
+  // Stacked stars make a backtracking matcher exponential in the segment
+  // length; this one has to come back as a plain 404.
+  let request = format!("GET /codes/{} HTTP/1.1\r\n\r\n", "a".repeat(500));
+  let raw = send_raw(request.as_bytes());
+  let (head, _) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 404 Not Found"), "{}", head);
+}
+
+#[test]
+fn test_closure_handlers() {
+  setup_test_server(create_test_server);
+  let raw = send_raw(b"GET /closure/empty HTTP/1.1\r\n\r\n");
+  let (head, _) = split_response(&raw);
+  assert!(head.starts_with("HTTP/1.1 404 Not Found"), "{}", head);
+  let raw = send_raw(b"GET /closure/path HTTP/1.1\r\n\r\n");
+  assert!(String::from_utf8_lossy(&raw).ends_with("closure /closure/path"));
+}
+
+fn demo_handle_order_id(request: &Request) -> Response {
+  Response::builder().body(format!("id {}", request.params["id"]))
+}
+
+fn demo_handle_order_digits(request: &Request) -> Response {
+  Response::builder().body(format!("digits {}", request.params["n"]))
+}
+
+#[test]
+fn test_overlapping_constraints_in_registration_order() {
+  setup_test_server(create_test_server);
+  // Both constraints accept 42; the one registered first wins.
+  let raw = send_raw(b"GET /order/42 HTTP/1.1\r\n\r\n");
+  assert!(String::from_utf8_lossy(&raw).ends_with("id 42"));
+  // Too large for a u64, so it falls through to the pattern.
+  let raw = send_raw(b"GET /order/99999999999999999999999 HTTP/1.1\r\n\r\n");
+  assert!(String::from_utf8_lossy(&raw).ends_with("digits 99999999999999999999999"));
+}
+
+fn demo_handle_big(_request: &Request) -> Response {
+  Response::builder().body(vec![b'x'; 64 << 20])
+}
+
+#[test]
+fn test_write_timeout_covers_whole_response() {
+  use std::io::{Read, Write};
+  use std::time::{Duration, Instant};
+
+  let mut server = Server::new("127.0.0.1:0", POOL_SIZE, None).unwrap();
+  server.set_timeouts(Timeouts {
+    write: Some(Duration::from_millis(500)),
+    ..Timeouts::default()
+  });
+  server.add_route("/big", Rt::GET, handler!(demo_handle_big));
+  let addr = spawn_server(server);
+
+  // Every read comes soon enough, but the whole response would take far
+  // longer than the write timeout.
+  let started = Instant::now();
+  let mut client = std::net::TcpStream::connect(&addr).unwrap();
+  client.write_all(b"GET /big HTTP/1.1\r\n\r\n").unwrap();
+  let mut buf = vec![0; 64 * 1024];
+  let mut received = 0;
+  while let Ok(n @ 1..) = client.read(&mut buf) {
+    received += n;
+    std::thread::sleep(Duration::from_millis(20));
+  }
+  assert!(received < 64 << 20, "{}", received);
+  assert!(started.elapsed() < Duration::from_secs(10), "{:?}", started.elapsed());
+}